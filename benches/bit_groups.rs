@@ -0,0 +1,60 @@
+//! Time the direct bit groups encodings against the route through the bit DFA, and check both
+//! match the same strings: `cargo bench --bench bit_groups`
+
+use std::time::{Duration, Instant};
+
+use regex_conv::{
+    bit_groups::{bit_groups_dfa, bit_groups_dfa_by_bits, BitGroupEncoding},
+    determine::determine_and_min_nfa,
+    hir_to_nfa::regex_to_nfa,
+};
+
+const PATTERNS: &[&str] = &[
+    "",
+    "a",
+    "a*",
+    "(ab|cde)*f?",
+    "\\x00[\\x80-\\xff]+z",
+    "[^\\n]{0,7}\\n",
+    "(GET|POST|PUT|DELETE) /[a-z0-9/]{1,32}\\?(id|user|token)=[A-Za-z0-9]{8,16} HTTP/1\\.[01]",
+    "[a-z0-9]{128}",
+    "session=[0-9a-f]{32}(; [a-z]+=[a-z0-9]*)*",
+];
+
+const ENCODINGS: &[BitGroupEncoding] = &[
+    BitGroupEncoding::Base64,
+    BitGroupEncoding::Base32,
+    BitGroupEncoding::Hex,
+];
+
+fn time<R>(f: impl FnOnce() -> R) -> (R, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+fn main() {
+    let all_bytes = (0..=255).collect::<Vec<_>>();
+    for pattern in PATTERNS {
+        let dfa = determine_and_min_nfa(regex_to_nfa(pattern).unwrap());
+
+        for encoding in ENCODINGS {
+            // the encodings of a pattern starting at any offset of a group
+            let any_offset =
+                determine_and_min_nfa(dfa.with_any_prefix(&all_bytes, encoding.group_len() - 1));
+
+            for (offset, dfa) in [("start", &dfa), ("any", &any_offset)] {
+                let (direct, direct_time) = time(|| bit_groups_dfa(dfa, *encoding));
+                let (by_bits, by_bits_time) = time(|| bit_groups_dfa_by_bits(dfa, *encoding));
+                assert!(
+                    direct.equivalent(&by_bits),
+                    "{encoding:?} of `{pattern}` at {offset} offset differs from the bit route"
+                );
+                println!(
+                    "{pattern:<24.24} {offset:<5} {:<6}  direct {direct_time:>12.3?}  by bits {by_bits_time:>12.3?}",
+                    format!("{encoding:?}")
+                );
+            }
+        }
+    }
+}
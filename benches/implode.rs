@@ -0,0 +1,123 @@
+//! Time [implode_dfa] on the bit DFAs of long patterns against the previous implementation,
+//! which enumerated the paths from every state: `cargo bench --bench implode`
+
+use std::time::{Duration, Instant};
+
+use regex_conv::{
+    automata::{Automata, Dfa, State},
+    determine::determine_and_min_nfa,
+    explode::explode_dfa,
+    hir_to_nfa::regex_to_nfa,
+    implode::implode_dfa,
+};
+
+const PATTERNS: &[(&str, &str)] = &[
+    (
+        "long literal",
+        "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor \
+         incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud \
+         exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat. Duis aute irure \
+         dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla pariatur",
+    ),
+    ("long class run", "[a-z0-9]{128}"),
+    (
+        "http request",
+        "(GET|POST|PUT|DELETE) /[a-z0-9/]{1,32}\\?(id|user|token)=[A-Za-z0-9]{8,16} HTTP/1\\.[01]",
+    ),
+    ("unbounded", "session=[0-9a-f]{32}(; [a-z]+=[a-z0-9]*)*"),
+];
+
+fn byte_bits(byte: &u8) -> Vec<bool> {
+    (0..8).rev().map(|i| (byte >> i) & 1 == 1).collect()
+}
+
+/// Values of the groups of `n` bits starting with `bits`
+fn group_values(n: usize, bits: Vec<bool>) -> Vec<u8> {
+    let prefix = bits
+        .iter()
+        .fold(0_u8, |value, bit| (value << 1) | *bit as u8);
+    let free = n - bits.len();
+    (0..1_u8 << free)
+        .map(|suffix| (prefix << free) | suffix)
+        .collect()
+}
+
+/// The previous [implode_dfa]: every path of up to `n` links from every state, scanning the
+/// links at each step
+fn baseline_implode<T: Clone, T2>(
+    dfa: &Dfa<T>,
+    n: usize,
+    merge_fn: impl Fn(Vec<T>) -> Vec<T2>,
+) -> Dfa<T2> {
+    let mut imploded = Automata::new();
+    imploded.initial_states = dfa.initial_states.clone();
+    imploded.accept_states = dfa.accept_states.clone();
+
+    for from in dfa.all_states() {
+        let mut implosions = Vec::new();
+        rec_implode(&mut implosions, &mut Vec::new(), dfa, from, n);
+        for (symbol_seq, to) in implosions {
+            for symbol in merge_fn(symbol_seq) {
+                imploded.link(from, to, symbol);
+            }
+        }
+    }
+
+    imploded
+}
+
+fn rec_implode<T: Clone>(
+    implosions: &mut Vec<(Vec<T>, State)>,
+    curr_seq: &mut Vec<T>,
+    dfa: &Dfa<T>,
+    from_state: State,
+    n: usize,
+) {
+    for link in dfa.links_from(from_state) {
+        curr_seq.push(link.symbol.clone());
+        if dfa.accept_states.contains(&link.to) || n == 1 {
+            implosions.push((curr_seq.clone(), link.to));
+        }
+        if n > 1 {
+            rec_implode(implosions, curr_seq, dfa, link.to, n - 1);
+        }
+        curr_seq.pop();
+    }
+}
+
+/// Fastest of a few runs
+fn time<R>(mut f: impl FnMut() -> R) -> Duration {
+    let start = Instant::now();
+    let mut best = Duration::MAX;
+    for _ in 0..10 {
+        let run = Instant::now();
+        std::hint::black_box(f());
+        best = best.min(run.elapsed());
+        if start.elapsed() > Duration::from_secs(2) {
+            break;
+        }
+    }
+    best
+}
+
+fn main() {
+    for (name, pattern) in PATTERNS {
+        let dfa = determine_and_min_nfa(regex_to_nfa(pattern).unwrap());
+        let exploded = determine_and_min_nfa(explode_dfa(&dfa, byte_bits).to_nfa());
+        let states = exploded.all_states().len();
+
+        for n in [4, 5, 6] {
+            let merge_fn = |bits| group_values(n, bits);
+            let imploded = determine_and_min_nfa(implode_dfa(&exploded, n, merge_fn).to_nfa());
+            let baseline = determine_and_min_nfa(baseline_implode(&exploded, n, merge_fn).to_nfa());
+            assert!(imploded.equivalent(&baseline), "{name}, {n} bits");
+
+            let elapsed = time(|| implode_dfa(&exploded, n, merge_fn));
+            let baseline_elapsed = time(|| baseline_implode(&exploded, n, merge_fn));
+            println!(
+                "{name:<16} {states:>6} bit states  implode {n} bits  {elapsed:>12.3?}  \
+                 baseline {baseline_elapsed:>12.3?}"
+            );
+        }
+    }
+}
@@ -1,8 +1,13 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fmt::{Display, Write},
+    hash::Hash,
 };
 
+use itertools::Itertools;
+
+use crate::counter::Counter;
+
 pub type State = u32;
 
 #[derive(Debug, Clone)]
@@ -22,6 +27,7 @@ pub struct Link<T> {
 pub type Nfa<T> = Automata<MaybeSymbol<T>>;
 pub type Dfa<T> = Automata<T>;
 
+#[derive(Debug, Clone)]
 pub enum MaybeSymbol<T> {
     Symbol(T),
     Epsilon,
@@ -55,6 +61,86 @@ impl<T> Dfa<T> {
                 .collect(),
         }
     }
+
+    /// Accept the same strings, preceded by up to `max_len` symbols of `alphabet`
+    pub fn with_any_prefix(&self, alphabet: &[T], max_len: usize) -> Nfa<T>
+    where
+        T: Clone,
+    {
+        let mut nfa = self.to_nfa();
+        let mut counter = Counter::new(self.max_state() + 1);
+        let initial_states = std::mem::take(&mut nfa.initial_states);
+
+        let mut prev = counter.next();
+        nfa.initial_states.insert(prev);
+        for len in 0..=max_len {
+            for initial in &initial_states {
+                nfa.link(prev, *initial, MaybeSymbol::Epsilon);
+            }
+            if len < max_len {
+                let next = counter.next();
+                for symbol in alphabet {
+                    nfa.link(prev, next, MaybeSymbol::Symbol(symbol.clone()));
+                }
+                prev = next;
+            }
+        }
+
+        nfa
+    }
+
+    /// Whether both DFAs accept the same strings, by walking the pairs of states reached by the
+    /// same strings
+    pub fn equivalent(&self, other: &Dfa<T>) -> bool
+    where
+        T: Clone + Hash + Eq,
+    {
+        let next_states = |dfa: &Dfa<T>| {
+            dfa.links
+                .iter()
+                .map(|link| ((link.from, link.symbol.clone()), link.to))
+                .collect::<HashMap<_, _>>()
+        };
+        let (self_next, other_next) = (next_states(self), next_states(other));
+        let symbols: HashSet<&T> = self
+            .links
+            .iter()
+            .chain(&other.links)
+            .map(|link| &link.symbol)
+            .collect();
+        let accepts = |dfa: &Dfa<T>, state: Option<State>| {
+            state.is_some_and(|state| dfa.accept_states.contains(&state))
+        };
+
+        let start = (
+            self.initial_states.iter().next().copied(),
+            other.initial_states.iter().next().copied(),
+        );
+        let mut explored = HashSet::from([start]);
+        let mut to_explore = vec![start];
+        while let Some((self_state, other_state)) = to_explore.pop() {
+            if accepts(self, self_state) != accepts(other, other_state) {
+                return false;
+            }
+            for symbol in &symbols {
+                let step = |next: &HashMap<(State, T), State>, state: Option<State>| {
+                    state.and_then(|state| next.get(&(state, (*symbol).clone())).copied())
+                };
+                let pair = (step(&self_next, self_state), step(&other_next, other_state));
+                if pair != (None, None) && explored.insert(pair) {
+                    to_explore.push(pair);
+                }
+            }
+        }
+
+        true
+    }
+}
+
+impl<T> Default for Automata<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T> Automata<T> {
@@ -66,6 +152,30 @@ impl<T> Automata<T> {
         }
     }
 
+    /// Automata accepting the strings of any of `automatas`, their states are renumbered so they
+    /// don't overlap: the states of an automata are shifted by the sum of `max_state() + 1` of the
+    /// previous ones
+    pub fn union(automatas: impl IntoIterator<Item = Automata<T>>) -> Self {
+        let mut union = Self::new();
+        let mut offset = 0;
+
+        for automata in automatas {
+            let next_offset = offset + automata.max_state() + 1;
+            union
+                .initial_states
+                .extend(automata.initial_states.iter().map(|state| state + offset));
+            union
+                .accept_states
+                .extend(automata.accept_states.iter().map(|state| state + offset));
+            for link in automata.links {
+                union.link(link.from + offset, link.to + offset, link.symbol);
+            }
+            offset = next_offset;
+        }
+
+        union
+    }
+
     pub fn invert(&mut self) {
         std::mem::swap(&mut self.accept_states, &mut self.initial_states);
         for link in self.links.iter_mut() {
@@ -114,11 +224,58 @@ impl<T> Automata<T> {
             .copied()
     }
 
+    /// States reachable from `from` by following links forward
+    pub fn reachable_from(&self, from: impl IntoIterator<Item = State>) -> HashSet<State> {
+        let successors = self
+            .links
+            .iter()
+            .map(|link| (link.from, link.to))
+            .into_group_map();
+        explore(&successors, from)
+    }
+
+    /// States from which one of `to` can be reached by following links forward
+    pub fn reaching_to(&self, to: impl IntoIterator<Item = State>) -> HashSet<State> {
+        let predecessors = self
+            .links
+            .iter()
+            .map(|link| (link.to, link.from))
+            .into_group_map();
+        explore(&predecessors, to)
+    }
+
+    /// Remove the states (and their links) that are not on a path from an initial state to an
+    /// accept state. The recognized language is unchanged.
+    pub fn trim(&mut self) {
+        let reachable = self.reachable_from(self.initial_states.iter().copied());
+        let reaching = self.reaching_to(self.accept_states.iter().copied());
+        let useful: HashSet<State> = reachable.intersection(&reaching).copied().collect();
+
+        self.initial_states.retain(|state| useful.contains(state));
+        self.accept_states.retain(|state| useful.contains(state));
+        self.links
+            .retain(|link| useful.contains(&link.from) && useful.contains(&link.to));
+    }
+
     pub fn link(&mut self, from: State, to: State, symbol: T) {
-        self.links.push(Link {
-            from,
-            symbol: symbol,
-            to,
-        })
+        self.links.push(Link { from, symbol, to })
     }
 }
+
+fn explore(
+    next_states: &HashMap<State, Vec<State>>,
+    from: impl IntoIterator<Item = State>,
+) -> HashSet<State> {
+    let mut explored: HashSet<State> = from.into_iter().collect();
+    let mut to_explore = explored.iter().copied().collect_vec();
+
+    while let Some(state) = to_explore.pop() {
+        for next in next_states.get(&state).into_iter().flatten() {
+            if explored.insert(*next) {
+                to_explore.push(*next);
+            }
+        }
+    }
+
+    explored
+}
@@ -0,0 +1,583 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
+};
+
+use itertools::Itertools;
+
+use crate::{
+    automata::{Automata, Dfa, State},
+    counter::Counter,
+    determine::determine_and_min_nfa_limited,
+    error::Error,
+    limits::{Limit, Limits},
+    transducer::Transducer,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Base85Alphabet {
+    /// Adobe Ascii85 / btoa: digits `!` to `u`, and `z` for a group of zeroes
+    Ascii85,
+    /// ZeroMQ Z85
+    Z85,
+}
+
+impl Base85Alphabet {
+    fn digits(&self) -> Vec<char> {
+        match self {
+            Base85Alphabet::Ascii85 => ('!'..='u').collect(),
+            Base85Alphabet::Z85 => {
+                "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#"
+                    .chars()
+                    .collect()
+            }
+        }
+    }
+}
+
+/// What to do with a group whose digits take more links than [Limits::max_group_links]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverGroupLinks {
+    /// Fail with [Error::LimitExceeded]
+    #[default]
+    Fail,
+    /// Widen the last constrained bytes of the group to any byte until its digits fit. The
+    /// group then also matches the values differing in these bytes: if the widened bytes matched
+    /// `m` of their `256^k` values, `1 - m / 256^k` of the values matched by the group are false
+    /// positives.
+    Widen,
+}
+
+type ByteClass = Vec<RangeInclusive<u8>>;
+
+/// Values of a group of bytes, all written with the digits of one group
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct GroupValues {
+    /// Bytes of the group, less than 4 when the pattern ends inside it
+    len: usize,
+    /// Sorted disjoint intervals
+    values: Vec<RangeInclusive<u64>>,
+}
+
+/// Encode the pattern in Base85: every group of 4 bytes is read as a big-endian number and
+/// written with 5 base-85 digits
+///
+/// Groups start on the first symbol of the pattern, see [base85_any_offset_dfa] for the other
+/// alignments. The value of a group is not a sequence of bit slices as in Base64: the paths of 4
+/// bytes are first turned into links reading the intervals of values of a group, then a
+/// transducer writes the digits of these values. A pattern ending in the middle of a group gives
+/// the digits only depending on its last bytes, as Ascii85 does for a truncated last group.
+///
+/// Base-85 digits don't follow the byte boundaries, so a group only constraining its last bytes
+/// (e.g. "any byte" then `[a-z]{3}`) takes a lot of states to be written exactly. Without a
+/// bound on the links of the digits of a group ([Limits::max_group_links]), such patterns can
+/// take hours.
+pub fn base85_dfa(dfa: &Dfa<u8>, alphabet: Base85Alphabet) -> Dfa<char> {
+    base85_dfa_limited(dfa, alphabet, OverGroupLinks::Fail, &Limits::default()).expect("no limits")
+}
+
+/// [base85_dfa], failing when the automata built go over `limits`, unless `over_group_links`
+/// widens the groups going over [Limits::max_group_links]
+pub fn base85_dfa_limited(
+    dfa: &Dfa<u8>,
+    alphabet: Base85Alphabet,
+    over_group_links: OverGroupLinks,
+    limits: &Limits,
+) -> Result<Dfa<char>, Error> {
+    let grouped = group_dfa(dfa, alphabet, over_group_links, limits)?;
+    let groups = grouped
+        .links
+        .iter()
+        .map(|link| link.symbol.clone())
+        .unique();
+    let transducer = Transducer::automata_substitution(groups, |group| digits_dfa(group, alphabet));
+    determine_and_min_nfa_limited(transducer.apply_limited(&grouped.to_nfa(), limits)?, limits)
+}
+
+/// DFA reading the values of the groups of 4 bytes of `dfa`
+fn group_dfa(
+    dfa: &Dfa<u8>,
+    alphabet: Base85Alphabet,
+    over_group_links: OverGroupLinks,
+    limits: &Limits,
+) -> Result<Dfa<GroupValues>, Error> {
+    let successors = dfa
+        .links
+        .iter()
+        .map(|link| ((link.from, link.to), link.symbol))
+        .into_group_map()
+        .into_iter()
+        .map(|((from, to), bytes)| (from, (to, byte_class(bytes))))
+        .into_group_map();
+
+    let mut grouped = Dfa::new();
+    grouped.initial_states = dfa.initial_states.clone();
+    grouped.accept_states = dfa.accept_states.clone();
+
+    // truncated groups end the match, they can't be followed by another group
+    let end = dfa.max_state() + 1;
+    grouped.accept_states.insert(end);
+
+    // only the states starting a group
+    let mut explored = dfa.initial_states.clone();
+    let mut to_explore = explored.iter().copied().collect_vec();
+
+    while let Some(from) = to_explore.pop() {
+        let mut groups = Vec::new();
+        rec_groups(
+            &mut groups,
+            &mut Vec::new(),
+            &successors,
+            &dfa.accept_states,
+            from,
+        );
+
+        let groups = groups.into_iter().into_group_map_by(|(classes, to)| {
+            let len = classes.len();
+            (if len == 4 { *to } else { end }, len)
+        });
+
+        for ((to, len), groups) in groups {
+            if len == 4 && explored.insert(to) {
+                to_explore.push(to);
+            }
+
+            let classes = groups.into_iter().map(|(classes, _)| classes).collect();
+            grouped.link(
+                from,
+                to,
+                group_values(classes, len, alphabet, over_group_links, limits)?,
+            );
+        }
+    }
+
+    Ok(grouped)
+}
+
+/// DFA reading the digits of the values of `group`
+fn digits_dfa(group: &GroupValues, alphabet: Base85Alphabet) -> Dfa<char> {
+    let mut digits_dfa = Dfa::new();
+    let (from, to) = (0, 1);
+    digits_dfa.initial_states.insert(from);
+    digits_dfa.accept_states.insert(to);
+    let mut counter = Counter::new(2);
+
+    if matches!(alphabet, Base85Alphabet::Ascii85)
+        && group.len == 4
+        && group
+            .values
+            .first()
+            .is_some_and(|first| *first.start() == 0)
+    {
+        digits_dfa.link(from, to, 'z');
+    }
+
+    let digit_links = DigitLinks {
+        values: &group.values,
+        len: group.len + 1,
+        digits: &alphabet.digits(),
+    };
+    digit_links.link(&mut digits_dfa, &mut counter, from, to);
+    digits_dfa
+}
+
+/// [base85_dfa] of the pattern starting at any offset in a group of 4 bytes
+pub fn base85_any_offset_dfa(dfa: &Dfa<u8>, alphabet: Base85Alphabet) -> Dfa<char> {
+    base85_any_offset_dfa_limited(dfa, alphabet, OverGroupLinks::Fail, &Limits::default())
+        .expect("no limits")
+}
+
+/// [base85_any_offset_dfa] within `limits`, see [base85_dfa_limited]
+///
+/// The first group of the offsets other than 0 starts with "any byte", it usually goes over
+/// [Limits::max_group_links] unless it is widened.
+pub fn base85_any_offset_dfa_limited(
+    dfa: &Dfa<u8>,
+    alphabet: Base85Alphabet,
+    over_group_links: OverGroupLinks,
+    limits: &Limits,
+) -> Result<Dfa<char>, Error> {
+    // unlike `Dfa::with_any_prefix`, each offset is encoded on its own so that the first group
+    // starts with exactly `offset` "any byte" classes
+    let aligned = (0..4).map(|offset| {
+        let mut prefixed = dfa.clone();
+        let mut counter = Counter::new(dfa.max_state() + 1);
+        for _ in 0..offset {
+            let initial = counter.next();
+            for state in std::mem::take(&mut prefixed.initial_states) {
+                for byte in 0..=255 {
+                    prefixed.link(initial, state, byte);
+                }
+            }
+            prefixed.initial_states.insert(initial);
+        }
+        base85_dfa_limited(&prefixed, alphabet, over_group_links, limits)
+    });
+    let aligned: Vec<_> = aligned.try_collect()?;
+    determine_and_min_nfa_limited(Automata::union(aligned).to_nfa(), limits)
+}
+
+/// Byte classes read by the paths of up to 4 links starting at `from`, with the state they reach
+///
+/// Paths shorter than 4 links are only kept when they reach an accept state.
+fn rec_groups(
+    groups: &mut Vec<(Vec<ByteClass>, State)>,
+    classes: &mut Vec<ByteClass>,
+    successors: &HashMap<State, Vec<(State, ByteClass)>>,
+    accept_states: &HashSet<State>,
+    from: State,
+) {
+    for (to, class) in successors.get(&from).into_iter().flatten() {
+        classes.push(class.clone());
+
+        if accept_states.contains(to) || classes.len() == 4 {
+            groups.push((classes.clone(), *to));
+        }
+
+        if classes.len() < 4 {
+            rec_groups(groups, classes, successors, accept_states, *to);
+        }
+
+        classes.pop();
+    }
+}
+
+fn byte_class(bytes: Vec<u8>) -> ByteClass {
+    bytes
+        .into_iter()
+        .sorted()
+        .dedup()
+        .map(|byte| byte..=byte)
+        .coalesce(|prev, next| {
+            if *prev.end() as u16 + 1 == *next.start() as u16 {
+                Ok(*prev.start()..=*next.end())
+            } else {
+                Err((prev, next))
+            }
+        })
+        .collect()
+}
+
+fn is_any(class: &ByteClass) -> bool {
+    *class == [0..=255]
+}
+
+const MAX_GROUP_VALUE: u64 = u32::MAX as u64;
+
+/// Values of the groups of `len` bytes read by `classes`, going over [Limits::max_group_links]
+/// as told by `over_group_links`
+fn group_values(
+    mut classes: Vec<Vec<ByteClass>>,
+    len: usize,
+    alphabet: Base85Alphabet,
+    over_group_links: OverGroupLinks,
+    limits: &Limits,
+) -> Result<GroupValues, Error> {
+    for classes in &mut classes {
+        classes.resize(4, vec![0..=255]);
+    }
+
+    loop {
+        // each interval takes at least a link, no need to enumerate millions of them
+        let checked = limits
+            .check(Limit::GroupLinks, interval_count(&classes))
+            .and_then(|()| {
+                let values = intervals(classes.iter().cloned());
+                let group = GroupValues { len, values };
+                let links = digits_dfa(&group, alphabet).links.len();
+                limits.check(Limit::GroupLinks, links).map(|()| group)
+            });
+        if checked.is_ok() || over_group_links == OverGroupLinks::Fail {
+            return checked;
+        }
+
+        // widen the last constrained class of each group
+        let mut widened = false;
+        for classes in &mut classes {
+            if let Some(last) = classes.iter().rposition(|class| !is_any(class)) {
+                classes[last] = vec![0..=255];
+                widened = true;
+            }
+        }
+        if !widened {
+            let values = vec![0..=MAX_GROUP_VALUE];
+            return Ok(GroupValues { len, values });
+        }
+    }
+}
+
+/// Intervals given by [intervals] before they are merged: each byte of the classes before the
+/// last constrained one gives an interval per range of the last one
+fn interval_count(groups: &[Vec<ByteClass>]) -> usize {
+    groups
+        .iter()
+        .map(|classes| {
+            let Some(last) = classes.iter().rposition(|class| !is_any(class)) else {
+                return 1;
+            };
+            let bytes_before: usize = classes[..last]
+                .iter()
+                .map(|class| class.iter().map(|range| range.len()).sum::<usize>())
+                .product();
+            bytes_before * classes[last].len()
+        })
+        .sum()
+}
+
+/// Values of the groups made of a byte of each class (the bytes after a truncated group being
+/// unknown), as sorted disjoint intervals
+fn intervals(groups: impl IntoIterator<Item = Vec<ByteClass>>) -> Vec<RangeInclusive<u64>> {
+    let mut intervals = Vec::new();
+    for mut classes in groups {
+        classes.resize(4, vec![0..=255]);
+        rec_intervals(&mut intervals, 0, &classes);
+    }
+
+    intervals
+        .into_iter()
+        .sorted_by_key(|interval| *interval.start())
+        .coalesce(|prev, next| {
+            if *next.start() <= *prev.end() + 1 {
+                Ok(*prev.start()..=*prev.end().max(next.end()))
+            } else {
+                Err((prev, next))
+            }
+        })
+        .collect()
+}
+
+fn rec_intervals(intervals: &mut Vec<RangeInclusive<u64>>, prefix: u64, classes: &[ByteClass]) {
+    let Some((class, rest)) = classes.split_first() else {
+        intervals.push(prefix..=prefix);
+        return;
+    };
+
+    // when the following bytes can be anything, each range of the class is a single interval
+    if rest.iter().all(is_any) {
+        let span = 256_u64.pow(rest.len() as u32);
+        for range in class {
+            let start = (prefix * 256 + *range.start() as u64) * span;
+            let end = (prefix * 256 + *range.end() as u64) * span + span - 1;
+            intervals.push(start..=end);
+        }
+        return;
+    }
+
+    for byte in class.iter().flat_map(|range| range.clone()) {
+        rec_intervals(intervals, prefix * 256 + byte as u64, rest);
+    }
+}
+
+/// Links reading the first `len` digits of the groups whose value is in `values`
+struct DigitLinks<'a> {
+    values: &'a [RangeInclusive<u64>],
+    len: usize,
+    digits: &'a [char],
+}
+
+impl DigitLinks<'_> {
+    /// After reading `depth` digits of value `start`, the group value is in
+    /// `start..start + weight(depth)`
+    fn link(&self, encoded: &mut Dfa<char>, counter: &mut Counter, from: State, to: State) {
+        // shared by the prefixes whose every following digits are accepted
+        let any_digits = (0..self.len)
+            .map(|depth| if depth == 0 { from } else { counter.next() })
+            .chain([to])
+            .collect_vec();
+        for (prev, next) in any_digits.iter().skip(1).tuple_windows() {
+            for digit in self.digits {
+                encoded.link(*prev, *next, *digit);
+            }
+        }
+
+        let mut to_explore = vec![(0, 0, from)];
+
+        while let Some((depth, start, state)) = to_explore.pop() {
+            let weight = weight(depth + 1);
+
+            for digit in 0..85 {
+                let next_start = start + digit * weight;
+                let next_end = (next_start + weight - 1).min(MAX_GROUP_VALUE);
+                if next_start > MAX_GROUP_VALUE {
+                    break;
+                }
+
+                let next_state = if depth + 1 == self.len {
+                    self.intersects(next_start, next_end).then_some(to)
+                } else if self.contains(next_start, next_end) && next_end - next_start + 1 == weight
+                {
+                    Some(any_digits[depth + 1])
+                } else if self.intersects(next_start, next_end) {
+                    let next_state = counter.next();
+                    to_explore.push((depth + 1, next_start, next_state));
+                    Some(next_state)
+                } else {
+                    None
+                };
+
+                if let Some(next_state) = next_state {
+                    encoded.link(state, next_state, self.digits[digit as usize]);
+                }
+            }
+        }
+    }
+
+    fn intersects(&self, start: u64, end: u64) -> bool {
+        let idx = self.values.partition_point(|values| *values.end() < start);
+        self.values
+            .get(idx)
+            .is_some_and(|values| *values.start() <= end)
+    }
+
+    fn contains(&self, start: u64, end: u64) -> bool {
+        let idx = self.values.partition_point(|values| *values.end() < start);
+        self.values
+            .get(idx)
+            .is_some_and(|values| *values.start() <= start && end <= *values.end())
+    }
+}
+
+/// Value of a digit read after `depth` digits
+fn weight(depth: usize) -> u64 {
+    85_u64.pow(5 - depth as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{determine::determine_and_min_nfa, hir_to_nfa::regex_to_nfa};
+
+    fn encode(group: [u8; 4]) -> String {
+        let digits = Base85Alphabet::Ascii85.digits();
+        let value = u32::from_be_bytes(group) as u64;
+        (0..5)
+            .map(|depth| digits[(value / weight(depth + 1) % 85) as usize])
+            .collect()
+    }
+
+    fn accepts(dfa: &Dfa<char>, text: &str) -> bool {
+        let mut states = dfa.initial_states.clone();
+        for c in text.chars() {
+            states = dfa
+                .links
+                .iter()
+                .filter(|link| states.contains(&link.from) && link.symbol == c)
+                .map(|link| link.to)
+                .collect();
+        }
+        states.iter().any(|state| dfa.accept_states.contains(state))
+    }
+
+    fn ascii85(regex: &str) -> Dfa<char> {
+        let dfa = determine_and_min_nfa(regex_to_nfa(regex).unwrap());
+        base85_dfa(&dfa, Base85Alphabet::Ascii85)
+    }
+
+    #[test]
+    fn exact_after_any_byte() {
+        let encoded = ascii85(r"[\x00-\xFF]abc");
+        for byte in 0..=255 {
+            assert!(accepts(&encoded, &encode([byte, b'a', b'b', b'c'])));
+            assert!(!accepts(&encoded, &encode([byte, b'a', b'b', b'd'])));
+        }
+    }
+
+    #[test]
+    fn zero_group() {
+        let encoded = ascii85(r"\x00\x00\x00\x00");
+        assert!(accepts(&encoded, "z"));
+        assert!(accepts(&encoded, "!!!!!"));
+    }
+
+    fn group_limits() -> Limits {
+        Limits {
+            max_group_links: Some(16384),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn group_links_limit() {
+        let dfa = |regex| determine_and_min_nfa(regex_to_nfa(regex).unwrap());
+        // 256 * 26 * 26 intervals
+        let err = base85_dfa_limited(
+            &dfa(r"[\x00-\xFF][a-z]{3}"),
+            Base85Alphabet::Ascii85,
+            OverGroupLinks::Fail,
+            &group_limits(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::LimitExceeded {
+                limit: Limit::GroupLinks,
+                max: 16384
+            }
+        ));
+        // the first group of the other offsets
+        let any_offset = base85_any_offset_dfa_limited(
+            &dfa("secret"),
+            Base85Alphabet::Z85,
+            OverGroupLinks::Fail,
+            &group_limits(),
+        );
+        assert!(any_offset.is_err());
+
+        let encoded = base85_dfa_limited(
+            &dfa("[a-z]{2}"),
+            Base85Alphabet::Ascii85,
+            OverGroupLinks::Fail,
+            &group_limits(),
+        )
+        .unwrap();
+        assert!(accepts(&encoded, &encode([b'q', b'r', 0, 0])[..3]));
+        assert!(!accepts(&encoded, &encode([b'q', b'!', 0, 0])[..3]));
+    }
+
+    #[test]
+    fn widened_false_positive_rate() {
+        let lower = vec![b'a'..=b'z'];
+        let classes = vec![vec![0..=255], lower.clone(), lower.clone(), lower];
+        let alphabet = Base85Alphabet::Ascii85;
+        let group = group_values(
+            vec![classes],
+            4,
+            alphabet,
+            OverGroupLinks::Widen,
+            &group_limits(),
+        );
+        // every constrained byte is widened, even 256 intervals of `[a-z]` second bytes take
+        // too many links
+        assert_eq!(group.unwrap().values, [0..=MAX_GROUP_VALUE]);
+        let exact = 256 * 26 * 26 * 26;
+        let false_positive_rate = 1.0 - exact as f64 / (MAX_GROUP_VALUE + 1) as f64;
+        assert!((false_positive_rate - (1.0 - (26.0 / 256.0_f64).powi(3))).abs() < 1e-9);
+
+        let dfa = determine_and_min_nfa(regex_to_nfa(r"[\x00-\xFF][a-z]{3}").unwrap());
+        let encoded =
+            base85_dfa_limited(&dfa, alphabet, OverGroupLinks::Widen, &group_limits()).unwrap();
+        assert!(accepts(&encoded, &encode([7, b'q', b'r', b's'])));
+        // false positive
+        assert!(accepts(&encoded, &encode([7, b'!', b'!', b'!'])));
+    }
+
+    #[test]
+    fn widened_last_bytes() {
+        let lower = vec![b'a'..=b'z'];
+        let classes = vec![lower.clone(), lower.clone(), lower.clone(), lower];
+        let alphabet = Base85Alphabet::Ascii85;
+        let group = group_values(
+            vec![classes],
+            4,
+            alphabet,
+            OverGroupLinks::Widen,
+            &group_limits(),
+        );
+        // the 2 last bytes are widened, the values of the 2 first ones are kept
+        let values = group.unwrap().values;
+        assert_eq!(values.len(), 26);
+        assert!(values
+            .iter()
+            .all(|values| values.end() - values.start() + 1 == 26 << 16));
+    }
+}
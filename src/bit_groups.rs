@@ -0,0 +1,432 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use itertools::Itertools;
+
+use crate::{
+    automata::{Dfa, State},
+    counter::Counter,
+    determine::{determine_and_min_nfa, determine_and_min_nfa_limited},
+    error::Error,
+    explode::explode_dfa,
+    implode::implode_dfa_limited,
+    labelled::Labelled,
+    limits::{Limit, Limits},
+    trace::GraphTrace,
+};
+
+/// Encodings writing each group of bits of the bytes with one digit
+#[derive(Debug, Clone, Copy)]
+pub enum BitGroupEncoding {
+    Base64,
+    /// RFC 4648 Base32
+    Base32,
+    /// Hex digits of any case
+    Hex,
+}
+
+impl BitGroupEncoding {
+    /// Bits written by a digit
+    pub fn bits(&self) -> usize {
+        match self {
+            BitGroupEncoding::Base64 => 6,
+            BitGroupEncoding::Base32 => 5,
+            BitGroupEncoding::Hex => 4,
+        }
+    }
+
+    /// Bytes after which the digits are aligned again
+    pub fn group_len(&self) -> usize {
+        match self {
+            BitGroupEncoding::Base64 => 3,
+            BitGroupEncoding::Base32 => 5,
+            BitGroupEncoding::Hex => 1,
+        }
+    }
+
+    /// Digits writing `value`
+    pub fn digits(&self, value: u8) -> Vec<char> {
+        let alphabet: &[u8] = match self {
+            BitGroupEncoding::Base64 => {
+                b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/"
+            }
+            BitGroupEncoding::Base32 => b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567",
+            BitGroupEncoding::Hex => b"0123456789ABCDEF",
+        };
+        let digit = alphabet[value as usize];
+        match self {
+            BitGroupEncoding::Hex => [digit, digit.to_ascii_lowercase()]
+                .into_iter()
+                .unique()
+                .map(char::from)
+                .collect(),
+            _ => vec![digit as char],
+        }
+    }
+}
+
+/// Encode the pattern in Base64, groups of 3 bytes starting on the first symbol of the pattern
+pub fn base64_dfa(dfa: &Dfa<u8>) -> Dfa<char> {
+    bit_groups_dfa(dfa, BitGroupEncoding::Base64)
+}
+
+/// Encode the pattern in Base32 (RFC 4648), groups of 5 bytes starting on the first symbol of
+/// the pattern
+pub fn base32_dfa(dfa: &Dfa<u8>) -> Dfa<char> {
+    bit_groups_dfa(dfa, BitGroupEncoding::Base32)
+}
+
+/// Encode the pattern in hex, with digits of any case
+pub fn hex_dfa(dfa: &Dfa<u8>) -> Dfa<char> {
+    bit_groups_dfa(dfa, BitGroupEncoding::Hex)
+}
+
+/// Write every group of bits with the digits of its value
+///
+/// A group truncated by the end of the pattern matches the digits of every value starting with
+/// its bits.
+pub fn bit_groups_dfa(dfa: &Dfa<u8>, encoding: BitGroupEncoding) -> Dfa<char> {
+    bit_groups_dfa_traced(dfa, encoding, &Limits::default(), None).expect("no limits")
+}
+
+/// [bit_groups_dfa] within `limits`, adding the automata of the digits of the groups to `trace`
+///
+/// The trace also gets the automata of the bit route (see [bit_groups_dfa_by_bits]), easier to
+/// read than the groups built from the bytes.
+pub fn bit_groups_dfa_traced(
+    dfa: &Dfa<u8>,
+    encoding: BitGroupEncoding,
+    limits: &Limits,
+    trace: Option<&mut GraphTrace>,
+) -> Result<Dfa<char>, Error> {
+    let (grouped, _) = group_bits(dfa, encoding, limits)?;
+    if let Some(trace) = trace {
+        let (exploded, imploded) = explode_implode(dfa, encoding, limits)?;
+        trace.push("exploded", &exploded);
+        trace.push("imploded", &imploded);
+        trace.push("grouped", &grouped);
+    }
+    determine_and_min_nfa_limited(grouped.to_nfa(), limits)
+}
+
+/// [bit_groups_dfa] through the DFA of the bits of the bytes, exploded, minimized, and whose
+/// groups of bits are imploded into digits. Same result, usually slower.
+pub fn bit_groups_dfa_by_bits(dfa: &Dfa<u8>, encoding: BitGroupEncoding) -> Dfa<char> {
+    let (_, imploded) = explode_implode(dfa, encoding, &Limits::default()).expect("no limits");
+    determine_and_min_nfa(imploded.to_nfa())
+}
+
+/// Minimized DFA of the bits of the bytes of `dfa`, and its groups of bits imploded into digits
+fn explode_implode(
+    dfa: &Dfa<u8>,
+    encoding: BitGroupEncoding,
+    limits: &Limits,
+) -> Result<(Dfa<bool>, Dfa<char>), Error> {
+    let exploded = explode_dfa(dfa, byte_bits);
+    let min_exploded = determine_and_min_nfa_limited(exploded.to_nfa(), limits)?;
+
+    let imploded = implode_dfa_limited(
+        &min_exploded,
+        encoding.bits(),
+        |bits| merge_bits(encoding, bits),
+        limits,
+    )?;
+    Ok((min_exploded, imploded))
+}
+
+/// Place in the bits of the bytes, between two digits
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum BitPosition {
+    /// Start of a byte read from a state of the byte DFA
+    ByteStart(State),
+    /// Inside a byte, `len` bits already written: the bits left of the bytes that can still be
+    /// read, with the state they lead to. Keyed by the bits left rather than the state reading
+    /// the byte, the places with the same continuations are merged.
+    InByte { len: u32, bytes: Vec<(u32, State)> },
+}
+
+/// Links from group to group of bits of the bytes of `dfa`, without building the bit DFA, and
+/// the grouped state of each state of `dfa` where a group starts
+///
+/// A group stays in the current byte, ends with it, or goes on in the next byte. Like the bit
+/// route, a group going on after an accept state is also truncated there.
+fn group_bits(
+    dfa: &Dfa<u8>,
+    encoding: BitGroupEncoding,
+    limits: &Limits,
+) -> Result<(Dfa<char>, HashMap<State, State>), Error> {
+    let group_len = encoding.bits() as u32;
+    let bytes_from = dfa
+        .links
+        .iter()
+        .map(|link| (link.from, (link.symbol as u32, link.to)))
+        .into_group_map();
+    let bytes_from = |state: State| bytes_from.get(&state).into_iter().flatten().copied();
+
+    let mut grouped = Dfa::new();
+    let mut states = HashMap::new();
+    let mut to_explore = Vec::new();
+    let mut counter = Counter::new(0);
+    let mut state_of = |position: BitPosition, to_explore: &mut Vec<BitPosition>| {
+        *states.entry(position.clone()).or_insert_with(|| {
+            to_explore.push(position);
+            counter.next()
+        })
+    };
+
+    for initial in &dfa.initial_states {
+        let state = state_of(BitPosition::ByteStart(*initial), &mut to_explore);
+        grouped.initial_states.insert(state);
+    }
+
+    while let Some(position) = to_explore.pop() {
+        let from = state_of(position.clone(), &mut to_explore);
+        let (len, bytes) = match position {
+            BitPosition::ByteStart(state) => {
+                if dfa.accept_states.contains(&state) {
+                    grouped.accept_states.insert(from);
+                }
+                (0, bytes_from(state).collect_vec())
+            }
+            BitPosition::InByte { len, bytes } => (len, bytes),
+        };
+        let left = 8 - len;
+
+        // (next position, value of the group, bits of the group)
+        let mut groups = HashSet::new();
+        if group_len < left {
+            for (value, bytes) in split_bits(bytes, left, group_len) {
+                let next = BitPosition::InByte {
+                    len: len + group_len,
+                    bytes,
+                };
+                groups.insert((next, value, group_len));
+            }
+        } else {
+            for (rest, to) in bytes {
+                if group_len == left {
+                    groups.insert((BitPosition::ByteStart(to), rest, group_len));
+                    continue;
+                }
+
+                if dfa.accept_states.contains(&to) {
+                    groups.insert((BitPosition::ByteStart(to), rest, left));
+                }
+                let next_len = group_len - left;
+                for (value, bytes) in split_bits(bytes_from(to), 8, next_len) {
+                    let next = BitPosition::InByte {
+                        len: next_len,
+                        bytes,
+                    };
+                    groups.insert((next, (rest << next_len) | value, group_len));
+                }
+            }
+        }
+
+        for (next, value, len) in groups {
+            let to = state_of(next, &mut to_explore);
+            let free = group_len - len;
+            for low in 0..1 << free {
+                for digit in encoding.digits(((value << free) | low) as u8) {
+                    grouped.link(from, to, digit);
+                }
+            }
+        }
+        limits.check(Limit::Links, grouped.links.len())?;
+    }
+
+    let byte_starts = states
+        .into_iter()
+        .filter_map(|(position, state)| match position {
+            BitPosition::ByteStart(byte_state) => Some((byte_state, state)),
+            BitPosition::InByte { .. } => None,
+        })
+        .collect();
+    Ok((grouped, byte_starts))
+}
+
+/// Group the bytes with `left` bits left by the value of their first `len` bits, keeping their
+/// other bits
+fn split_bits(
+    bytes: impl IntoIterator<Item = (u32, State)>,
+    left: u32,
+    len: u32,
+) -> BTreeMap<u32, Vec<(u32, State)>> {
+    let mut split = BTreeMap::<u32, Vec<_>>::new();
+    for (bits, to) in bytes {
+        let rest = bits & ((1 << (left - len)) - 1);
+        split
+            .entry(bits >> (left - len))
+            .or_default()
+            .push((rest, to));
+    }
+    for bytes in split.values_mut() {
+        bytes.sort_unstable();
+        bytes.dedup();
+    }
+    split
+}
+
+/// [bit_groups_dfa] of several labelled patterns
+pub fn bit_groups_labelled(dfa: &Labelled<u8>, encoding: BitGroupEncoding) -> Labelled<char> {
+    bit_groups_labelled_limited(dfa, encoding, &Limits::default()).expect("no limits")
+}
+
+/// [bit_groups_labelled], failing when the automata go over `limits`
+pub fn bit_groups_labelled_limited(
+    dfa: &Labelled<u8>,
+    encoding: BitGroupEncoding,
+    limits: &Limits,
+) -> Result<Labelled<char>, Error> {
+    let (grouped, byte_starts) = group_bits(&dfa.automata, encoding, limits)?;
+    // only the starts of the bytes accept
+    let labels = dfa
+        .labels
+        .iter()
+        .filter_map(|(state, patterns)| Some((*byte_starts.get(state)?, patterns.clone())))
+        .collect();
+
+    let grouped = Labelled {
+        automata: grouped.to_nfa(),
+        labels,
+    };
+    grouped.determine_and_min_limited(limits)
+}
+
+fn byte_bits(byte: &u8) -> Vec<bool> {
+    (0..8).rev().map(|i| (byte >> i) & 1 == 1).collect_vec()
+}
+
+fn merge_bits(encoding: BitGroupEncoding, list: Vec<bool>) -> Vec<char> {
+    let bits = encoding.bits();
+    assert!(list.len() <= bits);
+    if list.is_empty() {
+        return Vec::new();
+    }
+
+    let mut ones = 0_u8;
+    let mut zeroes = 0_u8;
+    for bit in 0..bits {
+        zeroes <<= 1;
+        ones <<= 1;
+        match list.get(bit) {
+            Some(true) => {
+                ones |= 1;
+                zeroes |= 1;
+            }
+            Some(false) => {}
+            None => {
+                ones |= 1;
+            }
+        }
+    }
+
+    let mask = !(zeroes ^ ones);
+    (0..1_u8 << bits)
+        .filter(|value| value & mask == zeroes & mask)
+        .flat_map(|value| encoding.digits(value))
+        .collect_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir_to_nfa::regex_to_nfa;
+
+    const PATTERNS: &[&str] = &[
+        "",
+        "a",
+        "a*",
+        "(ab|cde)*f?",
+        r"\x00[\x80-\xFF]+z",
+        "[^\n]{0,3}\n",
+    ];
+
+    const ENCODINGS: &[BitGroupEncoding] = &[
+        BitGroupEncoding::Base64,
+        BitGroupEncoding::Base32,
+        BitGroupEncoding::Hex,
+    ];
+
+    fn dfa(regex: &str) -> Dfa<u8> {
+        determine_and_min_nfa(regex_to_nfa(regex).unwrap())
+    }
+
+    #[test]
+    fn same_as_bit_route() {
+        let all_bytes = (0..=255).collect_vec();
+        for pattern in PATTERNS {
+            let dfa = dfa(pattern);
+            for encoding in ENCODINGS {
+                // the pattern starting at each offset in a group
+                for offset in 0..encoding.group_len() {
+                    let prefixed = determine_and_min_nfa(dfa.with_any_prefix(&all_bytes, offset));
+                    let direct = bit_groups_dfa(&prefixed, *encoding);
+                    let by_bits = bit_groups_dfa_by_bits(&prefixed, *encoding);
+                    assert!(
+                        direct.equivalent(&by_bits),
+                        "{encoding:?} of `{pattern}` after up to {offset} bytes"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn labelled_same_as_bit_route() {
+        let nfas = PATTERNS
+            .iter()
+            .map(|pattern| regex_to_nfa(pattern).unwrap());
+        let labelled = Labelled::union(nfas).determine_and_min();
+        for encoding in ENCODINGS {
+            let direct = bit_groups_labelled(&labelled, *encoding);
+
+            let min_exploded = labelled.explode(byte_bits).to_nfa().determine_and_min();
+            let imploded =
+                min_exploded.implode(encoding.bits(), |bits| merge_bits(*encoding, bits));
+            let by_bits = imploded.to_nfa().determine_and_min();
+
+            for (id, pattern) in PATTERNS.iter().enumerate() {
+                let dfa = |labelled: &Labelled<char>| {
+                    determine_and_min_nfa(labelled.pattern_dfa(id).to_nfa())
+                };
+                assert!(
+                    dfa(&direct).equivalent(&dfa(&by_bits)),
+                    "{encoding:?} of `{pattern}`"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn labelled_limits() {
+        let nfas = ["a(b|c)*", "(a|b)*a(a|b){6}"]
+            .iter()
+            .map(|pattern| regex_to_nfa(pattern).unwrap());
+        let union = Labelled::union(nfas);
+        let labelled = union.determine_and_min();
+        let states = Limits {
+            max_dfa_states: Some(labelled.automata.all_states().len() - 1),
+            ..Limits::default()
+        };
+        assert!(matches!(
+            union.determine_and_min_limited(&states),
+            Err(Error::LimitExceeded {
+                limit: Limit::DfaStates,
+                ..
+            })
+        ));
+
+        let encoded = bit_groups_labelled(&labelled, BitGroupEncoding::Base64);
+        let links = Limits {
+            max_links: Some(encoded.automata.links.len() - 1),
+            ..Limits::default()
+        };
+        assert!(matches!(
+            bit_groups_labelled_limited(&labelled, BitGroupEncoding::Base64, &links),
+            Err(Error::LimitExceeded {
+                limit: Limit::Links,
+                ..
+            })
+        ));
+    }
+}
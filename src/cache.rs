@@ -0,0 +1,105 @@
+use std::{fs, io, path::PathBuf, process};
+
+/// Directory of conversion results, each in a file named after the hash of its key
+///
+/// The key is stored in the file as well, so that a hash collision is a cache miss instead of a
+/// wrong result, and so is a hash of the value, so that a file corrupted on disk is a miss too.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Value stored for `key`, if any
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let content = fs::read(self.path(key)).ok()?;
+        let (len, rest) = content.split_first_chunk::<8>()?;
+        let (rest, hash) = rest.split_last_chunk::<8>()?;
+        let (stored_key, value) = rest.split_at_checked(u64::from_le_bytes(*len) as usize)?;
+        (stored_key == key.as_bytes() && u64::from_le_bytes(*hash) == fnv1a(value))
+            .then(|| value.to_vec())
+    }
+
+    /// Store `value` for `key`, replacing the previous one
+    pub fn put(&self, key: &str, value: &[u8]) -> io::Result<()> {
+        let mut content = (key.len() as u64).to_le_bytes().to_vec();
+        content.extend_from_slice(key.as_bytes());
+        content.extend_from_slice(value);
+        content.extend_from_slice(&fnv1a(value).to_le_bytes());
+
+        // renamed once complete, concurrent runs never read a partial file
+        let path = self.path(key);
+        let tmp = path.with_extension(format!("{}.tmp", process::id()));
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &path)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.bin", fnv1a(key.as_bytes())))
+    }
+}
+
+/// 64-bit FNV-1a hash, unlike [std::hash::DefaultHasher] it is the same across Rust versions
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty cache directory, unique to the test
+    fn cache(name: &str) -> Cache {
+        let dir = std::env::temp_dir().join(format!("regex-conv-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Cache::new(dir).unwrap()
+    }
+
+    #[test]
+    fn put_get() {
+        let cache = cache("put-get");
+        assert_eq!(cache.get("a"), None);
+        cache.put("a", b"value").unwrap();
+        cache.put("b", b"").unwrap();
+        assert_eq!(cache.get("a").as_deref(), Some(&b"value"[..]));
+        assert_eq!(cache.get("b").as_deref(), Some(&b""[..]));
+        cache.put("a", b"other").unwrap();
+        assert_eq!(cache.get("a").as_deref(), Some(&b"other"[..]));
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn corrupted_files_are_misses() {
+        let cache = cache("corrupted");
+        cache.put("key", b"some value").unwrap();
+        let path = cache.path("key");
+        let content = fs::read(&path).unwrap();
+
+        for idx in 0..content.len() {
+            let mut corrupted = content.clone();
+            corrupted[idx] ^= 0x01;
+            fs::write(&path, &corrupted).unwrap();
+            assert_eq!(cache.get("key"), None, "{idx}");
+        }
+        for len in 0..content.len() {
+            fs::write(&path, &content[..len]).unwrap();
+            assert_eq!(cache.get("key"), None, "{len}");
+        }
+        fs::write(&path, [&content[..], b"x"].concat()).unwrap();
+        assert_eq!(cache.get("key"), None);
+
+        // another key with the same file name
+        fs::write(&path, &content).unwrap();
+        fs::rename(&path, cache.path("other")).unwrap();
+        assert_eq!(cache.get("other"), None);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
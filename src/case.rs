@@ -0,0 +1,69 @@
+use crate::{
+    automata::Dfa, determine::determine_and_min_nfa_limited, error::Error, explode::explode_dfa,
+    limits::Limits,
+};
+
+/// Match every ASCII letter of the pattern in both cases
+pub fn case_insensitive_dfa(dfa: &Dfa<u8>) -> Dfa<u8> {
+    case_insensitive_dfa_limited(dfa, &Limits::default()).expect("no limits")
+}
+
+/// [case_insensitive_dfa], failing when the automata built go over `limits`
+pub fn case_insensitive_dfa_limited(dfa: &Dfa<u8>, limits: &Limits) -> Result<Dfa<u8>, Error> {
+    let mut both_cases = dfa.clone();
+    both_cases.links.extend(
+        explode_dfa(dfa, |byte: &u8| vec![swap_case(*byte)])
+            .links
+            .into_iter()
+            .filter(|link| link.symbol.is_ascii_alphabetic()),
+    );
+    // `a` and `A` may lead to different states
+    determine_and_min_nfa_limited(both_cases.to_nfa(), limits)
+}
+
+fn swap_case(byte: u8) -> u8 {
+    if byte.is_ascii_lowercase() {
+        byte.to_ascii_uppercase()
+    } else {
+        byte.to_ascii_lowercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{determine::determine_and_min_nfa, hir_to_nfa::regex_to_nfa};
+
+    fn dfa(regex: &str) -> Dfa<u8> {
+        determine_and_min_nfa(regex_to_nfa(regex).unwrap())
+    }
+
+    #[test]
+    fn ascii_letters() {
+        let cases = [
+            ("abc", "[aA][bB][cC]"),
+            ("Ab1-", "[aA][bB]1-"),
+            ("[a-z]+", "[a-zA-Z]+"),
+            // `a` and `A` lead to different states
+            ("ax|Ay", "[aA][xXyY]"),
+        ];
+        for (regex, expected) in cases {
+            let folded = case_insensitive_dfa(&dfa(regex));
+            assert!(folded.equivalent(&dfa(expected)), "{regex}");
+        }
+    }
+
+    #[test]
+    fn other_bytes_unchanged() {
+        // the neighbours of the letters, Latin-1 and UTF-8 `é`/`É`
+        for regex in [
+            r"[0-9@\[`{]",
+            r"\xE9\xC9",
+            r"\xC3\xA9\xC3\x89",
+            r"[\x80-\xFF]",
+        ] {
+            let folded = case_insensitive_dfa(&dfa(regex));
+            assert!(folded.equivalent(&dfa(regex)), "{regex}");
+        }
+    }
+}
@@ -9,6 +9,7 @@ impl Counter {
         Self { curr: start }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> State {
         let curr = self.curr;
         self.curr = self.curr.checked_add(1).unwrap();
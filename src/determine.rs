@@ -1,122 +1,158 @@
 use std::{
-    collections::{BTreeSet, HashMap, HashSet},
+    collections::{BTreeSet, HashMap},
     fmt::Debug,
     hash::Hash,
 };
 
+use itertools::Itertools;
+
 use crate::{
-    automata::{Dfa, MaybeSymbol, Nfa, State},
+    automata::{Dfa, Link, MaybeSymbol, Nfa, State},
     counter::Counter,
+    error::Error,
+    limits::{Limit, Limits},
 };
 
 /// A multi state is a collection of NFA states mapping to one DFA state
 ///
 /// [BTreeSet] because [HashSet] doesn't impl [Hash]
-type MultiState = BTreeSet<State>;
+pub(crate) type MultiState = BTreeSet<State>;
+
+pub fn determine_and_min_nfa<T>(nfa: Nfa<T>) -> Dfa<T>
+where
+    T: Eq + Hash + Clone + Debug,
+{
+    determine_and_min_nfa_limited(nfa, &Limits::default()).expect("no limits")
+}
 
-pub fn determine_and_min_nfa<T>(mut nfa: Nfa<T>) -> Dfa<T>
+/// [determine_and_min_nfa], failing when a DFA (the final one or the reversed one built by the
+/// minimization) goes over `limits`
+pub fn determine_and_min_nfa_limited<T>(mut nfa: Nfa<T>, limits: &Limits) -> Result<Dfa<T>, Error>
 where
     T: Eq + Hash + Clone + Debug,
 {
     nfa.invert();
-    let mut dfa = determine_nfa(&nfa);
+    let mut dfa = determine_nfa_states(&nfa, limits)?.0;
     dfa.invert();
     let nfa2 = dfa.to_nfa();
-    determine_nfa(&nfa2)
+    Ok(determine_nfa_states(&nfa2, limits)?.0)
 }
 
-fn determine_nfa<T>(nfa: &Nfa<T>) -> Dfa<T>
+/// Subset construction, also returning the NFA states of each DFA state
+pub(crate) fn determine_nfa_states<T>(
+    nfa: &Nfa<T>,
+    limits: &Limits,
+) -> Result<(Dfa<T>, HashMap<MultiState, State>), Error>
 where
     T: Eq + Hash + Clone + Debug,
 {
-    let mut dfa = Dfa::new();
-    let mut counter = Counter::new(0);
-    let mut state_mapping: HashMap<MultiState, State> = Default::default();
-
-    macro_rules! nfa2dfa {
-        ($multi_state:expr) => {
-            *state_mapping
-                .entry($multi_state.clone())
-                .or_insert_with(|| {
-                    let next = counter.next();
-                    let is_accept = $multi_state
-                        .iter()
-                        .any(|state| nfa.accept_states.contains(state));
-                    if is_accept {
-                        dfa.accept_states.insert(next);
-                    }
-                    next
-                })
-        };
-    }
+    let links_from = links_by_from(nfa);
+    let epsilons_from = epsilons_by_from(nfa);
+
+    let mut subsets = Subsets {
+        nfa,
+        limits,
+        dfa: Dfa::new(),
+        counter: Counter::new(0),
+        state_mapping: HashMap::new(),
+        to_explore: Vec::new(),
+    };
 
     let initial_state = nfa.initial_states.iter().copied().collect();
-    let initial_state = normalize_multi_state(nfa, initial_state);
-    dfa.initial_states.insert(nfa2dfa!(initial_state));
-
-    let mut to_explore: HashSet<MultiState> = Default::default();
-    let mut explored: HashSet<MultiState> = Default::default();
-
-    to_explore.insert(initial_state.clone());
-    explored.insert(initial_state);
-
-    while !to_explore.is_empty() {
-        for norm_from in std::mem::take(&mut to_explore) {
-            let mut to_by_symbol: HashMap<T, MultiState> = HashMap::new();
-
-            for link in &nfa.links {
-                if !norm_from.contains(&link.from) {
-                    continue;
-                }
+    let initial_state = normalize_multi_state(&epsilons_from, initial_state);
+    let initial = subsets.dfa_state(initial_state)?;
+    subsets.dfa.initial_states.insert(initial);
+
+    while let Some((norm_from, dfa_from)) = subsets.to_explore.pop() {
+        let mut to_by_symbol: HashMap<&T, Vec<State>> = HashMap::new();
+        for link in norm_from
+            .iter()
+            .flat_map(|from| links_from.get(from).into_iter().flatten())
+        {
+            if let MaybeSymbol::Symbol(symbol) = &link.symbol {
+                to_by_symbol.entry(symbol).or_default().push(link.to);
+            }
+        }
 
-                let MaybeSymbol::Symbol(symbol) = &link.symbol else {
-                    continue;
-                };
+        for (symbol, to) in to_by_symbol {
+            let norm_to = normalize_multi_state(&epsilons_from, to.into_iter().collect());
+            let dfa_to = subsets.dfa_state(norm_to)?;
+            subsets.dfa.link(dfa_from, dfa_to, symbol.clone());
+            limits.check(Limit::Links, subsets.dfa.links.len())?;
+        }
+    }
 
-                to_by_symbol
-                    .entry(symbol.clone())
-                    .or_default()
-                    .insert(link.to);
-            }
+    Ok((subsets.dfa, subsets.state_mapping))
+}
 
-            let dfa_from = nfa2dfa!(norm_from);
+/// DFA being built by the subset construction
+struct Subsets<'a, T> {
+    nfa: &'a Nfa<T>,
+    limits: &'a Limits,
+    dfa: Dfa<T>,
+    counter: Counter,
+    state_mapping: HashMap<MultiState, State>,
+    /// New multi states, with their DFA state
+    to_explore: Vec<(MultiState, State)>,
+}
 
-            for (symbol, to) in to_by_symbol {
-                let norm_to = normalize_multi_state(nfa, to);
-                let dfa_to = nfa2dfa!(norm_to);
-                dfa.link(dfa_from, dfa_to, symbol);
+impl<T> Subsets<'_, T> {
+    /// DFA state of `multi_state`, added when it's new
+    fn dfa_state(&mut self, multi_state: MultiState) -> Result<State, Error> {
+        if let Some(state) = self.state_mapping.get(&multi_state) {
+            return Ok(*state);
+        }
 
-                if explored.contains(&norm_to) {
-                    continue;
-                }
-                explored.insert(norm_to.clone());
-                to_explore.insert(norm_to.clone());
-            }
+        self.limits
+            .check(Limit::DfaStates, self.state_mapping.len() + 1)?;
+        let next = self.counter.next();
+        let is_accept = multi_state
+            .iter()
+            .any(|state| self.nfa.accept_states.contains(state));
+        if is_accept {
+            self.dfa.accept_states.insert(next);
         }
+        self.state_mapping.insert(multi_state.clone(), next);
+        self.to_explore.push((multi_state, next));
+        Ok(next)
     }
+}
 
-    dfa
+/// Links of the NFA indexed by the state they start from
+fn links_by_from<T>(nfa: &Nfa<T>) -> HashMap<State, Vec<&Link<MaybeSymbol<T>>>> {
+    nfa.links
+        .iter()
+        .map(|link| (link.from, link))
+        .into_group_map()
 }
 
-fn normalize_multi_state<T>(nfa: &Nfa<T>, from: MultiState) -> MultiState {
-    let mut to_explore = from.clone();
-    let mut explored = MultiState::new();
-    let mut result = MultiState::new();
+/// Targets of the epsilon links of the NFA indexed by the state they start from, the closures
+/// only follow these links, not every link of the states of a large multi state
+fn epsilons_by_from<T>(nfa: &Nfa<T>) -> HashMap<State, Vec<State>> {
+    nfa.links
+        .iter()
+        .filter(|link| matches!(link.symbol, MaybeSymbol::Epsilon))
+        .map(|link| (link.from, link.to))
+        .into_group_map()
+}
 
-    while !to_explore.is_empty() {
-        for state in std::mem::take(&mut to_explore) {
-            if explored.contains(&state) {
-                continue;
-            }
-            result.insert(state);
-            explored.insert(state);
-            for link in nfa.links_from(state) {
-                if let MaybeSymbol::Epsilon = &link.symbol {
-                    to_explore.insert(link.to);
-                }
+fn normalize_multi_state(
+    epsilons_from: &HashMap<State, Vec<State>>,
+    mut multi_state: MultiState,
+) -> MultiState {
+    let mut to_explore = multi_state
+        .iter()
+        .filter(|state| epsilons_from.contains_key(state))
+        .copied()
+        .collect_vec();
+
+    while let Some(state) = to_explore.pop() {
+        for next in epsilons_from.get(&state).into_iter().flatten() {
+            if multi_state.insert(*next) {
+                to_explore.push(*next);
             }
         }
     }
 
-    result
+    multi_state
 }
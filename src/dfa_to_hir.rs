@@ -1,54 +1,93 @@
+use std::collections::{HashMap, HashSet};
+
 use itertools::Itertools;
-use regex_syntax::hir::{Class, ClassUnicode, ClassUnicodeRange, Hir, Repetition};
+use regex_syntax::hir::{
+    Class, ClassBytes, ClassBytesRange, ClassUnicode, ClassUnicodeRange, Hir, HirKind, Literal,
+    Repetition,
+};
 
 use crate::{
-    automata::{Automata, Dfa, Link},
+    automata::{Automata, Dfa, Link, State},
     counter::Counter,
 };
 
-pub fn dfa_to_hir(dfa_origin: &Dfa<char>) -> Hir {
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HirOptions {
+    /// Emit greedy repetitions (`*`) instead of lazy ones (`*?`)
+    pub greedy: bool,
+    /// Fold repeated sub-expressions into `+`, `?` and `{n,m}`
+    pub counted_repetitions: bool,
+}
+
+/// Symbols of the DFA given to [dfa_to_hir]
+pub trait HirSymbol {
+    /// A class matching only this symbol
+    fn to_hir(&self) -> Hir;
+}
+
+impl HirSymbol for char {
+    fn to_hir(&self) -> Hir {
+        Hir::class(Class::Unicode(ClassUnicode::new([ClassUnicodeRange::new(
+            *self, *self,
+        )])))
+    }
+}
+
+impl HirSymbol for u8 {
+    fn to_hir(&self) -> Hir {
+        Hir::class(Class::Bytes(ClassBytes::new([ClassBytesRange::new(
+            *self, *self,
+        )])))
+    }
+}
+
+/// Extract a regex from a DFA by state elimination
+///
+/// The empty language gives [Hir::fail] and the language only made of the empty string gives
+/// [Hir::empty].
+pub fn dfa_to_hir<T: HirSymbol>(dfa_origin: &Dfa<T>, options: &HirOptions) -> Hir {
     let mut dfa = Dfa {
-        accept_states: Default::default(),
-        initial_states: Default::default(),
+        accept_states: dfa_origin.accept_states.clone(),
+        initial_states: dfa_origin.initial_states.clone(),
         links: dfa_origin
             .links
             .iter()
             .map(|link| Link {
                 from: link.from,
                 to: link.to,
-                symbol: Hir::class(Class::Unicode(ClassUnicode::new([ClassUnicodeRange::new(
-                    link.symbol,
-                    link.symbol,
-                )]))),
+                symbol: link.symbol.to_hir(),
             })
             .collect(),
     };
+    // states off the paths from an initial state to an accept state would leave dangling links
+    dfa.trim();
 
     let mut counter = Counter::new(dfa_origin.max_state() + 1);
     let start = counter.next();
     let end = counter.next();
 
-    for init_state in &dfa_origin.initial_states {
-        dfa.link(start, *init_state, Hir::empty());
+    for init_state in std::mem::take(&mut dfa.initial_states) {
+        dfa.link(start, init_state, Hir::empty());
     }
 
-    for accept_state in &dfa_origin.accept_states {
-        dfa.link(*accept_state, end, Hir::empty());
+    for accept_state in std::mem::take(&mut dfa.accept_states) {
+        dfa.link(accept_state, end, Hir::empty());
     }
 
     merge_sibling_edges(&mut dfa);
 
-    let mut all_states = dfa.all_states();
-    all_states.remove(&start);
-    all_states.remove(&end);
+    let mut remaining = dfa.all_states();
+    remaining.remove(&start);
+    remaining.remove(&end);
 
-    for rip in all_states {
+    while let Some(rip) = next_to_eliminate(&dfa, &remaining) {
+        remaining.remove(&rip);
         debug_assert!(dfa.links_from_to(rip, rip).count() <= 1);
 
         let self_loop = dfa.links_from_to(rip, rip).exactly_one().ok();
         let self_loop = self_loop.map(|self_loop| {
             Hir::repetition(Repetition {
-                greedy: false,
+                greedy: options.greedy,
                 min: 0,
                 max: None,
                 sub: Box::new(self_loop.symbol.clone()),
@@ -67,7 +106,6 @@ pub fn dfa_to_hir(dfa_origin: &Dfa<char>) -> Hir {
                 if *from == rip || *to == rip {
                     continue;
                 }
-                debug_assert_ne!(from, to);
 
                 let in_sym = Hir::alternation(incomings.iter().map(|l| l.symbol.clone()).collect());
                 let out_sym =
@@ -88,28 +126,308 @@ pub fn dfa_to_hir(dfa_origin: &Dfa<char>) -> Hir {
         debug_assert_eq!(dfa.links_from_to(rip, rip).count(), 0);
     }
 
-    assert_eq!(dfa.links.len(), 1);
+    debug_assert!(dfa.links.len() <= 1);
 
-    dfa.links.remove(0).symbol
+    // no link left from `start` to `end`: no accept state can be reached
+    let Some(Link { symbol: hir, .. }) = dfa.links.pop() else {
+        return Hir::fail();
+    };
+
+    if options.counted_repetitions {
+        count_repetitions(&hir, options.greedy)
+    } else {
+        hir
+    }
 }
 
-fn merge_sibling_edges(dfa: &mut Automata<Hir>) {
-    for states in dfa.all_states().iter().permutations(2) {
-        let [a, b] = states.as_slice() else { panic!() };
-        let from = **a;
-        let to = **b;
-
-        let hirs = dfa
-            .links_from_to(from, to)
-            .map(|l| l.symbol.clone())
-            .collect_vec();
-
-        if hirs.len() <= 1 {
-            continue;
+/// Eliminating the states with the fewest neighbours first keeps the intermediate regexes small,
+/// an arbitrary order can make the result exponentially larger
+fn next_to_eliminate(dfa: &Automata<Hir>, remaining: &HashSet<State>) -> Option<State> {
+    let mut neighbours: HashMap<State, (usize, usize)> = HashMap::new();
+    for link in dfa.links.iter().filter(|link| link.from != link.to) {
+        neighbours.entry(link.from).or_default().1 += 1;
+        neighbours.entry(link.to).or_default().0 += 1;
+    }
+
+    remaining.iter().copied().min_by_key(|state| {
+        let (incomings, outgoings) = neighbours.get(state).copied().unwrap_or_default();
+        (incomings * outgoings, *state)
+    })
+}
+
+/// Fold `XX*` into `X+`, `XXX` into `X{3}`, `(?:|X)` into `X?`...
+fn count_repetitions(hir: &Hir, greedy: bool) -> Hir {
+    match hir.kind() {
+        // `aaa` is a single literal
+        HirKind::Literal(_) if split_literal(hir).len() > 1 => {
+            count_concat_repetitions(std::slice::from_ref(hir), greedy)
+        }
+        HirKind::Concat(items) => count_concat_repetitions(items, greedy),
+        HirKind::Alternation(items) => {
+            let optional = items
+                .iter()
+                .any(|item| matches!(item.kind(), HirKind::Empty));
+            let alternation = Hir::alternation(
+                items
+                    .iter()
+                    .filter(|item| !matches!(item.kind(), HirKind::Empty))
+                    .map(|item| count_repetitions(item, greedy))
+                    .collect(),
+            );
+            if optional {
+                repeat(alternation, 0, Some(1), greedy)
+            } else {
+                alternation
+            }
         }
+        HirKind::Repetition(rep) => Hir::repetition(Repetition {
+            sub: Box::new(count_repetitions(&rep.sub, greedy)),
+            min: rep.min,
+            max: rep.max,
+            greedy,
+        }),
+        _ => hir.clone(),
+    }
+}
+
+/// [count_repetitions] of the concatenation of `items`
+fn count_concat_repetitions(items: &[Hir], greedy: bool) -> Hir {
+    let mut folded: Vec<(Hir, u32, Option<u32>)> = Vec::new();
+
+    for item in items.iter().flat_map(split_literal) {
+        let item = count_repetitions(&item, greedy);
+        let (sub, min, max) = match item.kind() {
+            HirKind::Repetition(rep) => ((*rep.sub).clone(), rep.min, rep.max),
+            _ => (item, 1, Some(1)),
+        };
+
+        let (mut min, mut max) = (min, max);
+
+        // `abab(?:ab)*`: the previous literal was split into single characters
+        let chars = split_literal(&sub);
+        while chars.len() > 1 && folded.len() >= chars.len() {
+            let tail = &folded[folded.len() - chars.len()..];
+            let repeated = tail
+                .iter()
+                .zip(&chars)
+                .all(|((prev, prev_min, prev_max), c)| {
+                    prev == c && *prev_min == 1 && *prev_max == Some(1)
+                });
+            if !repeated {
+                break;
+            }
+            folded.truncate(folded.len() - chars.len());
+            min += 1;
+            max = max.map(|max| max + 1);
+        }
+
+        match folded.last_mut() {
+            Some((last, last_min, last_max)) if *last == sub => {
+                *last_min += min;
+                *last_max = last_max.zip(max).map(|(a, b)| a + b);
+            }
+            _ => folded.push((sub, min, max)),
+        }
+    }
+
+    Hir::concat(
+        folded
+            .into_iter()
+            .map(|(sub, min, max)| repeat(sub, min, max, greedy))
+            .collect(),
+    )
+}
+
+/// Split a literal into one literal per character so runs of the same character can be counted
+fn split_literal(hir: &Hir) -> Vec<Hir> {
+    let HirKind::Literal(Literal(bytes)) = hir.kind() else {
+        return vec![hir.clone()];
+    };
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text
+            .chars()
+            .map(|c| Hir::literal(c.to_string().into_bytes()))
+            .collect(),
+        Err(_) => bytes.iter().map(|byte| Hir::literal([*byte])).collect(),
+    }
+}
+
+fn repeat(sub: Hir, min: u32, max: Option<u32>, greedy: bool) -> Hir {
+    match (sub.kind(), min, max) {
+        // `aa` reads better than `a{2}`
+        (HirKind::Literal(_), 2, Some(2)) => Hir::concat(vec![sub.clone(), sub]),
+        // `(?:X{1,n})?` is `X{0,n}`
+        (HirKind::Repetition(rep), 0, Some(1)) if rep.min <= 1 => Hir::repetition(Repetition {
+            min: 0,
+            max: rep.max,
+            greedy,
+            sub: rep.sub.clone(),
+        }),
+        _ => Hir::repetition(Repetition {
+            min,
+            max,
+            greedy,
+            sub: Box::new(sub),
+        }),
+    }
+}
 
-        dfa.remove_links(from, to);
+/// Merge the links sharing the same `from` and `to` (self loops included) into one alternation
+fn merge_sibling_edges(dfa: &mut Automata<Hir>) {
+    let siblings = std::mem::take(&mut dfa.links)
+        .into_iter()
+        .into_group_map_by(|link| (link.from, link.to));
 
+    for ((from, to), links) in siblings {
+        let hirs = links.into_iter().map(|link| link.symbol).collect_vec();
         dfa.link(from, to, Hir::alternation(hirs));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        determine::determine_and_min_nfa,
+        hir_to_nfa::{hir_to_nfa, regex_to_nfa},
+    };
+
+    fn dfa(initial: &[State], accept: &[State], links: &[(State, u8, State)]) -> Dfa<u8> {
+        let mut dfa = Dfa::new();
+        dfa.initial_states.extend(initial);
+        dfa.accept_states.extend(accept);
+        for (from, symbol, to) in links {
+            dfa.link(*from, *to, *symbol);
+        }
+        dfa
+    }
+
+    /// Whether `hir` matches the same strings as `regex`
+    fn matches_like(hir: &Hir, regex: &str) -> bool {
+        let actual = determine_and_min_nfa(hir_to_nfa(hir).unwrap());
+        let expected = determine_and_min_nfa(regex_to_nfa(regex).unwrap());
+        actual.equivalent(&expected)
+    }
+
+    #[test]
+    fn empty_language() {
+        let options = HirOptions::default();
+        assert_eq!(dfa_to_hir(&dfa(&[0], &[], &[]), &options), Hir::fail());
+        assert_eq!(
+            dfa_to_hir(&dfa(&[0], &[], &[(0, b'a', 1)]), &options),
+            Hir::fail()
+        );
+        assert_eq!(dfa_to_hir(&dfa(&[], &[], &[]), &options), Hir::fail());
+    }
+
+    #[test]
+    fn only_empty_string() {
+        let options = HirOptions::default();
+        assert_eq!(dfa_to_hir(&dfa(&[0], &[0], &[]), &options), Hir::empty());
+        // the link leads to a dead state
+        assert_eq!(
+            dfa_to_hir(&dfa(&[0], &[0], &[(0, b'a', 1)]), &options),
+            Hir::empty()
+        );
+    }
+
+    #[test]
+    fn several_initial_states() {
+        let dfa = dfa(&[0, 1], &[2], &[(0, b'a', 2), (1, b'b', 2), (2, b'c', 2)]);
+        let hir = dfa_to_hir(&dfa, &HirOptions::default());
+        assert!(matches_like(&hir, "[ab]c*"));
+    }
+
+    #[test]
+    fn dead_and_unreachable_states() {
+        let dfa = dfa(
+            &[0],
+            &[1, 5],
+            &[
+                (0, b'a', 1),
+                // dead end
+                (0, b'b', 2),
+                (2, b'c', 3),
+                // unreachable
+                (4, b'd', 5),
+                (4, b'e', 1),
+            ],
+        );
+        let hir = dfa_to_hir(&dfa, &HirOptions::default());
+        assert!(matches_like(&hir, "a"));
+    }
+
+    fn parse(regex: &str) -> Hir {
+        regex_syntax::ParserBuilder::new()
+            .unicode(false)
+            .utf8(false)
+            .build()
+            .parse(regex)
+            .unwrap()
+    }
+
+    #[test]
+    fn folded_repetitions() {
+        let cases = [
+            ("abab(?:ab)*", "(?:ab){2,}"),
+            ("(?:|x)", "x?"),
+            ("aaa", "a{3}"),
+            ("aa", "(?:aa)"),
+            ("xaaay", "(?:xa{3}y)"),
+            ("a(?:a)*", "a+"),
+            ("aa(?:a)?", "a{2,3}"),
+            ("a?a?a?", "a{0,3}"),
+            ("(?:|a{1,3})", "a{0,3}"),
+            ("abab", "(?:abab)"),
+            (r"\xFF\xFF\xFF", r"(?-u:\xFF){3}"),
+        ];
+        for (regex, expected) in cases {
+            let folded = count_repetitions(&parse(regex), true);
+            assert_eq!(folded.to_string(), expected, "{regex}");
+            assert!(matches_like(&folded, regex), "{regex}");
+        }
+    }
+
+    #[test]
+    fn counted_repetitions_option() {
+        let options = HirOptions {
+            greedy: true,
+            counted_repetitions: true,
+        };
+        for regex in ["abab(ab)*", "(|x)y", "aaa", "a(bc){3,5}d", "(ab|cd)*x{2}"] {
+            let dfa = determine_and_min_nfa(regex_to_nfa(regex).unwrap());
+            let hir = dfa_to_hir(&dfa, &options);
+            assert!(matches_like(&hir, regex), "{regex}: {hir}");
+        }
+        let dfa = determine_and_min_nfa(regex_to_nfa("aaaa").unwrap());
+        assert_eq!(dfa_to_hir(&dfa, &options).to_string(), "a{4}");
+    }
+
+    #[test]
+    fn fewest_neighbours_first() {
+        let mut hirs = Automata::new();
+        // 1 has 2 incoming and 2 outgoing neighbours, 2 and 3 have 1 of each, the self loop of
+        // 3 doesn't count
+        for (from, to) in [(0, 1), (4, 1), (1, 2), (1, 3), (2, 5), (3, 5), (3, 3)] {
+            hirs.link(from, to, Hir::literal(*b"a"));
+        }
+        let remaining = HashSet::from([1, 2, 3]);
+        assert_eq!(next_to_eliminate(&hirs, &remaining), Some(2));
+        assert_eq!(next_to_eliminate(&hirs, &HashSet::from([1, 3])), Some(3));
+        assert_eq!(next_to_eliminate(&hirs, &HashSet::new()), None);
+    }
+
+    #[test]
+    fn elimination_order_keeps_regexes_small() {
+        // eliminated in the order of the states, they give 7006 and 712 chars
+        let octet = "(25[0-5]|2[0-4][0-9]|1?[0-9]?[0-9])";
+        let ipv4 = format!(r"{octet}(\.{octet}){{3}}");
+        let cases = [(ipv4.as_str(), 600), ("(ab|ba|aa)*(c|d)(ab|ba)*", 60)];
+        for (regex, max_len) in cases {
+            let dfa = determine_and_min_nfa(regex_to_nfa(regex).unwrap());
+            let hir = dfa_to_hir(&dfa, &HirOptions::default());
+            assert!(hir.to_string().len() <= max_len, "{regex}: {hir}");
+            assert!(matches_like(&hir, regex), "{regex}");
+        }
+    }
+}
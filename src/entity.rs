@@ -0,0 +1,271 @@
+use itertools::Itertools;
+
+use crate::{
+    automata::{Dfa, MaybeSymbol, State},
+    determine::determine_and_min_nfa_limited,
+    error::Error,
+    limits::Limits,
+    transducer::{Transducer, Transduction},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum EntityFlavor {
+    /// Named entities of HTML 5, `&#X41`, and `;` optional where browsers allow it
+    Html,
+    /// The 5 named entities of XML, `&#x41;` only, `;` required
+    Xml,
+}
+
+/// Named entities that browsers also recognize without the final `;`
+const LEGACY_ENTITIES: &[(&str, u8)] = &[
+    ("quot", b'"'),
+    ("QUOT", b'"'),
+    ("amp", b'&'),
+    ("AMP", b'&'),
+    ("lt", b'<'),
+    ("LT", b'<'),
+    ("gt", b'>'),
+    ("GT", b'>'),
+    ("COPY", 0xA9),
+    ("REG", 0xAE),
+];
+
+/// The Latin-1 entities of HTML 4, from `&nbsp;` (0xA0) to `&yuml;` (0xFF), also legacy ones
+const LATIN1_ENTITIES: &[&str] = &[
+    "nbsp", "iexcl", "cent", "pound", "curren", "yen", "brvbar", "sect", "uml", "copy", "ordf",
+    "laquo", "not", "shy", "reg", "macr", "deg", "plusmn", "sup2", "sup3", "acute", "micro",
+    "para", "middot", "cedil", "sup1", "ordm", "raquo", "frac14", "frac12", "frac34", "iquest",
+    "Agrave", "Aacute", "Acirc", "Atilde", "Auml", "Aring", "AElig", "Ccedil", "Egrave", "Eacute",
+    "Ecirc", "Euml", "Igrave", "Iacute", "Icirc", "Iuml", "ETH", "Ntilde", "Ograve", "Oacute",
+    "Ocirc", "Otilde", "Ouml", "times", "Oslash", "Ugrave", "Uacute", "Ucirc", "Uuml", "Yacute",
+    "THORN", "szlig", "agrave", "aacute", "acirc", "atilde", "auml", "aring", "aelig", "ccedil",
+    "egrave", "eacute", "ecirc", "euml", "igrave", "iacute", "icirc", "iuml", "eth", "ntilde",
+    "ograve", "oacute", "ocirc", "otilde", "ouml", "divide", "oslash", "ugrave", "uacute", "ucirc",
+    "uuml", "yacute", "thorn", "yuml",
+];
+
+/// HTML 5 entities of ASCII characters, only recognized with the final `;`
+const HTML5_ENTITIES: &[(&str, u8)] = &[
+    ("Tab", b'\t'),
+    ("NewLine", b'\n'),
+    ("excl", b'!'),
+    ("num", b'#'),
+    ("dollar", b'$'),
+    ("percnt", b'%'),
+    ("apos", b'\''),
+    ("lpar", b'('),
+    ("rpar", b')'),
+    ("ast", b'*'),
+    ("midast", b'*'),
+    ("plus", b'+'),
+    ("comma", b','),
+    ("period", b'.'),
+    ("sol", b'/'),
+    ("colon", b':'),
+    ("semi", b';'),
+    ("equals", b'='),
+    ("quest", b'?'),
+    ("commat", b'@'),
+    ("lsqb", b'['),
+    ("lbrack", b'['),
+    ("bsol", b'\\'),
+    ("rsqb", b']'),
+    ("rbrack", b']'),
+    ("Hat", b'^'),
+    ("lowbar", b'_'),
+    ("UnderBar", b'_'),
+    ("grave", b'`'),
+    ("DiacriticalGrave", b'`'),
+    ("lcub", b'{'),
+    ("lbrace", b'{'),
+    ("verbar", b'|'),
+    ("vert", b'|'),
+    ("VerticalLine", b'|'),
+    ("rcub", b'}'),
+    ("rbrace", b'}'),
+];
+
+const XML_ENTITIES: &[(&str, u8)] = &[
+    ("quot", b'"'),
+    ("amp", b'&'),
+    ("lt", b'<'),
+    ("gt", b'>'),
+    ("apos", b'\''),
+];
+
+/// Match every rendering of the pattern with character references: each byte is written
+/// literally, as a named entity when one exists, or as a decimal `&#65;` or hex `&#x41;`
+/// reference with any number of leading zeroes, the forms being freely mixed
+///
+/// Symbols are read as Latin-1 characters, non-ASCII bytes are only matched as references. A
+/// reference followed by a digit it would read written literally keeps its `;`.
+pub fn entity_encode_dfa(dfa: &Dfa<u8>, flavor: EntityFlavor) -> Dfa<u8> {
+    entity_encode_dfa_limited(dfa, flavor, &Limits::default()).expect("no limits")
+}
+
+/// [entity_encode_dfa], failing when the automata built go over `limits`
+pub fn entity_encode_dfa_limited(
+    dfa: &Dfa<u8>,
+    flavor: EntityFlavor,
+    limits: &Limits,
+) -> Result<Dfa<u8>, Error> {
+    // 0: between characters, 1 + byte: in `&#0*`, 257 + byte: in `&#x0*`, 513 and 514: after
+    // the decimal and hex digits, 515 and 516: after a decimal and hex reference without `;`,
+    // where a digit can't be written literally
+    let decimal_state = |byte: u8| 1 + byte as State;
+    let hex_state = |byte: u8| 257 + byte as State;
+    let (decimal_end, hex_end) = (513, 514);
+    let (after_decimal, after_hex) = (515, 516);
+
+    let mut transducer = Transducer::new();
+    transducer.initial_states.insert(0);
+    transducer
+        .accept_states
+        .extend([0, after_decimal, after_hex]);
+
+    let mut write = |from: State, to: State, input: Option<u8>, output: &[u8]| {
+        let input = match input {
+            Some(byte) => MaybeSymbol::Symbol(byte),
+            None => MaybeSymbol::Epsilon,
+        };
+        let output = output.to_vec();
+        transducer.link(from, to, Transduction { input, output });
+    };
+
+    let html = matches!(flavor, EntityFlavor::Html);
+
+    for byte in 0..=255_u8 {
+        for from in [0, after_decimal, after_hex] {
+            let read_by_reference = (from == after_decimal && byte.is_ascii_digit())
+                || (from == after_hex && byte.is_ascii_hexdigit());
+            if (byte.is_ascii_graphic() || b" \t\r\n".contains(&byte)) && !read_by_reference {
+                write(from, 0, Some(byte), &[byte]);
+            }
+
+            for (name, terminated) in named_entities(byte, flavor) {
+                write(from, 0, Some(byte), format!("&{name};").as_bytes());
+                if !terminated {
+                    write(from, 0, Some(byte), format!("&{name}").as_bytes());
+                }
+            }
+
+            write(from, decimal_state(byte), Some(byte), b"&#");
+            let hex_prefixes: &[&[u8]] = if html { &[b"&#x", b"&#X"] } else { &[b"&#x"] };
+            for prefix in hex_prefixes {
+                write(from, hex_state(byte), Some(byte), prefix);
+            }
+        }
+
+        write(decimal_state(byte), decimal_state(byte), None, b"0");
+        write(
+            decimal_state(byte),
+            decimal_end,
+            None,
+            byte.to_string().as_bytes(),
+        );
+
+        write(hex_state(byte), hex_state(byte), None, b"0");
+        for digits in hex_digits(byte) {
+            write(hex_state(byte), hex_end, None, &digits);
+        }
+    }
+
+    write(decimal_end, 0, None, b";");
+    write(hex_end, 0, None, b";");
+    if html {
+        write(decimal_end, after_decimal, None, b"");
+        write(hex_end, after_hex, None, b"");
+    }
+
+    determine_and_min_nfa_limited(transducer.apply_limited(&dfa.to_nfa(), limits)?, limits)
+}
+
+/// Named entities of `byte`, and whether the final `;` is required
+fn named_entities(byte: u8, flavor: EntityFlavor) -> Vec<(&'static str, bool)> {
+    match flavor {
+        EntityFlavor::Xml => XML_ENTITIES
+            .iter()
+            .filter(|(_, entity_byte)| *entity_byte == byte)
+            .map(|(name, _)| (*name, true))
+            .collect(),
+        EntityFlavor::Html => {
+            let legacy = LEGACY_ENTITIES
+                .iter()
+                .filter(|(_, entity_byte)| *entity_byte == byte)
+                .map(|(name, _)| *name)
+                .chain(
+                    byte.checked_sub(0xA0)
+                        .map(|idx| LATIN1_ENTITIES[idx as usize]),
+                )
+                .map(|name| (name, false));
+            let html5 = HTML5_ENTITIES
+                .iter()
+                .filter(|(_, entity_byte)| *entity_byte == byte)
+                .map(|(name, _)| (*name, true));
+            legacy.chain(html5).collect()
+        }
+    }
+}
+
+/// `byte` in hex without leading zeroes, with digits of any case
+fn hex_digits(byte: u8) -> Vec<Vec<u8>> {
+    format!("{byte:X}")
+        .bytes()
+        .map(|digit| {
+            [digit, digit.to_ascii_lowercase()]
+                .into_iter()
+                .unique()
+                .collect_vec()
+        })
+        .multi_cartesian_product()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{determine::determine_and_min_nfa, hir_to_nfa::regex_to_nfa};
+
+    fn accepts(regex: &str, flavor: EntityFlavor, text: &[u8]) -> bool {
+        let dfa = determine_and_min_nfa(regex_to_nfa(regex).unwrap());
+        let dfa = entity_encode_dfa(&dfa, flavor);
+        let mut states = dfa.initial_states.clone();
+        for byte in text {
+            states = dfa
+                .links
+                .iter()
+                .filter(|link| states.contains(&link.from) && link.symbol == *byte)
+                .map(|link| link.to)
+                .collect();
+        }
+        states.iter().any(|state| dfa.accept_states.contains(state))
+    }
+
+    #[test]
+    fn references() {
+        for text in [
+            b"&#65;".as_slice(),
+            b"&#0065;",
+            b"&#x41;",
+            b"&#X041",
+            b"&#65",
+        ] {
+            assert!(accepts("A", EntityFlavor::Html, text));
+        }
+        assert!(accepts("A", EntityFlavor::Xml, b"&#x41;"));
+        assert!(!accepts("A", EntityFlavor::Xml, b"&#X41;"));
+        assert!(!accepts("A", EntityFlavor::Xml, b"&#65"));
+    }
+
+    #[test]
+    fn semicolon_before_digits() {
+        assert!(!accepts("A1", EntityFlavor::Html, b"&#651"));
+        assert!(accepts("A1", EntityFlavor::Html, b"&#65;1"));
+        assert!(accepts("A1", EntityFlavor::Html, b"&#65&#49"));
+        assert!(!accepts("A1", EntityFlavor::Html, b"&#x411"));
+        assert!(!accepts("Ab", EntityFlavor::Html, b"&#x41b"));
+        assert!(accepts("Ab", EntityFlavor::Html, b"&#x41;b"));
+        assert!(accepts("Ag", EntityFlavor::Html, b"&#x41g"));
+        assert!(accepts("Ab", EntityFlavor::Html, b"&#65b"));
+    }
+}
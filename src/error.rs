@@ -0,0 +1,40 @@
+use std::fmt::{self, Display};
+
+use crate::limits::Limit;
+
+/// Why a pattern can't be converted
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid regex syntax
+    Parse(Box<regex_syntax::Error>),
+    /// Valid regex using a construct that can't be turned into an automaton
+    Unsupported(&'static str),
+    /// Invalid JSON document, or not describing an automaton
+    Json(String),
+    /// Invalid binary automaton
+    Binary(&'static str),
+    /// The conversion went over one of its [Limits](crate::limits::Limits)
+    LimitExceeded { limit: Limit, max: usize },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(err) => write!(f, "invalid regex: {err}"),
+            Error::Unsupported(construct) => write!(f, "unsupported regex construct: {construct}"),
+            Error::Json(message) => write!(f, "invalid JSON: {message}"),
+            Error::Binary(message) => write!(f, "invalid binary automaton: {message}"),
+            Error::LimitExceeded { limit, max } => {
+                write!(f, "{limit} over the limit of {max}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<regex_syntax::Error> for Error {
+    fn from(err: regex_syntax::Error) -> Self {
+        Error::Parse(Box::new(err))
+    }
+}
@@ -0,0 +1,239 @@
+use itertools::Itertools;
+
+use crate::{
+    automata::{Dfa, MaybeSymbol, State},
+    determine::determine_and_min_nfa_limited,
+    error::Error,
+    limits::Limits,
+    transducer::{Transducer, Transduction},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum EscapeScheme {
+    /// `\x41`, `\101`, `\n`
+    C,
+    /// `A`, `\n`
+    Json,
+    /// `\x41`, `A`, `\u{41}`, `\101`, `\n`, `\A`
+    JavaScript,
+    /// `\x41`, `\101`, `A`, `\U00000041`, `\n`
+    Python,
+}
+
+/// Match every rendering of the pattern in a string literal of `scheme`: each byte is written
+/// literally when allowed or escaped, the forms being freely mixed
+///
+/// Symbols are read as Latin-1 characters, non-ASCII bytes are only matched escaped. An escape
+/// reading a variable number of digits (C `\x41`, octal `\12`) is not followed by a digit it
+/// would read written literally.
+pub fn escape_dfa(dfa: &Dfa<u8>, scheme: EscapeScheme) -> Dfa<u8> {
+    escape_dfa_limited(dfa, scheme, &Limits::default()).expect("no limits")
+}
+
+/// [escape_dfa], failing when the automata built go over `limits`
+pub fn escape_dfa_limited(
+    dfa: &Dfa<u8>,
+    scheme: EscapeScheme,
+    limits: &Limits,
+) -> Result<Dfa<u8>, Error> {
+    let mut transducer = Transducer::new();
+    transducer.initial_states.insert(FREE);
+    transducer.accept_states.extend(STATES);
+
+    for byte in 0..=255 {
+        for rendering in renderings(byte, scheme) {
+            let to = match rendering.as_slice() {
+                [b'\\', b'x', ..] if matches!(scheme, EscapeScheme::C) => AFTER_HEX,
+                [b'\\', digits @ ..] if digits.len() < 3 && digits.iter().all(is_octal) => {
+                    AFTER_SHORT_OCTAL
+                }
+                _ => FREE,
+            };
+            for from in STATES {
+                let first = rendering[0];
+                let read_by_escape = match from {
+                    AFTER_HEX => first.is_ascii_hexdigit(),
+                    AFTER_SHORT_OCTAL => is_octal(&first),
+                    _ => false,
+                };
+                if !read_by_escape {
+                    let input = MaybeSymbol::Symbol(byte);
+                    let output = rendering.clone();
+                    transducer.link(from, to, Transduction { input, output });
+                }
+            }
+        }
+    }
+
+    determine_and_min_nfa_limited(transducer.apply_limited(&dfa.to_nfa(), limits)?, limits)
+}
+
+/// Any rendering can follow
+const FREE: State = 0;
+/// After a C `\x` escape, which reads every following hex digit
+const AFTER_HEX: State = 1;
+/// After an octal escape of less than 3 digits
+const AFTER_SHORT_OCTAL: State = 2;
+const STATES: [State; 3] = [FREE, AFTER_HEX, AFTER_SHORT_OCTAL];
+
+fn is_octal(byte: &u8) -> bool {
+    (b'0'..=b'7').contains(byte)
+}
+
+fn renderings(byte: u8, scheme: EscapeScheme) -> Vec<Vec<u8>> {
+    let mut renderings = Vec::new();
+    let json = matches!(scheme, EscapeScheme::Json);
+
+    // the string may be delimited by `"` or `'`, JSON only uses `"`
+    if byte.is_ascii_graphic() && byte != b'\\' && !(json && byte == b'"') || byte == b' ' {
+        renderings.push(vec![byte]);
+    }
+    if byte == b'\\' || byte == b'"' || byte == b'\'' && !json {
+        renderings.push(vec![b'\\', byte]);
+    }
+
+    let short_escapes: &[(u8, u8)] = match scheme {
+        EscapeScheme::C => &[
+            (7, b'a'),
+            (8, b'b'),
+            (12, b'f'),
+            (10, b'n'),
+            (13, b'r'),
+            (9, b't'),
+            (11, b'v'),
+            (b'?', b'?'),
+        ],
+        EscapeScheme::Json => &[
+            (8, b'b'),
+            (12, b'f'),
+            (10, b'n'),
+            (13, b'r'),
+            (9, b't'),
+            (b'/', b'/'),
+        ],
+        EscapeScheme::JavaScript => &[
+            (8, b'b'),
+            (12, b'f'),
+            (10, b'n'),
+            (13, b'r'),
+            (9, b't'),
+            (11, b'v'),
+        ],
+        EscapeScheme::Python => &[
+            (7, b'a'),
+            (8, b'b'),
+            (12, b'f'),
+            (10, b'n'),
+            (13, b'r'),
+            (9, b't'),
+            (11, b'v'),
+        ],
+    };
+    for (escaped, letter) in short_escapes {
+        if *escaped == byte {
+            renderings.push(vec![b'\\', *letter]);
+        }
+    }
+
+    match scheme {
+        EscapeScheme::C => {
+            renderings.extend(escapes(b"\\x", byte, 2, b""));
+            renderings.extend(octal_escapes(byte));
+        }
+        EscapeScheme::Json => {
+            renderings.extend(escapes(b"\\u", byte, 4, b""));
+        }
+        EscapeScheme::JavaScript => {
+            renderings.extend(escapes(b"\\x", byte, 2, b""));
+            renderings.extend(escapes(b"\\u", byte, 4, b""));
+            // leading zeroes are allowed, up to the 6 digits of the largest code point
+            for width in format!("{byte:x}").len()..=6 {
+                renderings.extend(escapes(b"\\u{", byte, width, b"}"));
+            }
+            renderings.extend(octal_escapes(byte));
+            // non-strict code: escaping a character without special meaning gives itself
+            if byte.is_ascii_graphic() && !b"0123456789xubfnrtv".contains(&byte) {
+                renderings.push(vec![b'\\', byte]);
+            }
+        }
+        EscapeScheme::Python => {
+            renderings.extend(escapes(b"\\x", byte, 2, b""));
+            renderings.extend(escapes(b"\\u", byte, 4, b""));
+            renderings.extend(escapes(b"\\U", byte, 8, b""));
+            renderings.extend(octal_escapes(byte));
+        }
+    }
+
+    renderings.into_iter().unique().collect()
+}
+
+/// `prefix` then `byte` in hex on `width` digits of any case, then `suffix`
+fn escapes(prefix: &[u8], byte: u8, width: usize, suffix: &[u8]) -> Vec<Vec<u8>> {
+    format!("{byte:0width$X}")
+        .bytes()
+        .map(|digit| {
+            [digit, digit.to_ascii_lowercase()]
+                .into_iter()
+                .unique()
+                .collect_vec()
+        })
+        .multi_cartesian_product()
+        .map(|digits| [prefix, &digits, suffix].concat())
+        .collect()
+}
+
+/// `\101`, and the shorter forms without leading zeroes (`\0`, `\12`)
+fn octal_escapes(byte: u8) -> Vec<Vec<u8>> {
+    let octal = format!("{byte:o}");
+    (octal.len()..=3)
+        .map(|width| format!("\\{byte:0width$o}").into_bytes())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{determine::determine_and_min_nfa, hir_to_nfa::regex_to_nfa};
+
+    fn accepts(regex: &str, scheme: EscapeScheme, text: &[u8]) -> bool {
+        let dfa = escape_dfa(&determine_and_min_nfa(regex_to_nfa(regex).unwrap()), scheme);
+        let mut states = dfa.initial_states.clone();
+        for byte in text {
+            states = dfa
+                .links
+                .iter()
+                .filter(|link| states.contains(&link.from) && link.symbol == *byte)
+                .map(|link| link.to)
+                .collect();
+        }
+        states.iter().any(|state| dfa.accept_states.contains(state))
+    }
+
+    #[test]
+    fn hex_escape_reads_every_digit() {
+        assert!(accepts("AB", EscapeScheme::C, br"\x41\x42"));
+        assert!(accepts("AB", EscapeScheme::C, br"\x41\102"));
+        assert!(accepts("AG", EscapeScheme::C, br"\x41G"));
+        assert!(!accepts("AB", EscapeScheme::C, br"\x41B"));
+        assert!(!accepts("A1", EscapeScheme::C, br"\x411"));
+        // Python reads 2 digits
+        assert!(accepts("AB", EscapeScheme::Python, br"\x41B"));
+    }
+
+    #[test]
+    fn short_octal_escape() {
+        assert!(!accepts(r"\x012", EscapeScheme::C, br"\12"));
+        assert!(accepts(r"\x012", EscapeScheme::C, br"\0012"));
+        assert!(accepts(r"\x012", EscapeScheme::C, br"\1\x32"));
+        assert!(accepts(r"\x018", EscapeScheme::C, br"\18"));
+        assert!(accepts(r"\n", EscapeScheme::C, br"\12"));
+    }
+
+    #[test]
+    fn js_code_point_escape() {
+        for text in [br"\u{41}".as_slice(), br"\u{041}", br"\u{000041}"] {
+            assert!(accepts("A", EscapeScheme::JavaScript, text));
+        }
+        assert!(!accepts("A", EscapeScheme::JavaScript, br"\u{0000041}"));
+    }
+}
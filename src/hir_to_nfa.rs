@@ -1,20 +1,49 @@
-use regex_syntax::hir::{Class, Hir, HirKind, Literal};
+use regex_syntax::{
+    hir::{Class, Hir, HirKind, Literal},
+    ParserBuilder,
+};
 
 use crate::{
     automata::{Automata, MaybeSymbol::*, Nfa, State},
     counter::Counter,
+    error::Error,
+    limits::{Limit, Limits},
 };
 
-pub fn hir_to_nfa(hir: &Hir) -> Nfa<u8> {
+/// Parse `regex` as a byte regex (`\xFF` is a byte, `.` any byte) and build its NFA
+pub fn regex_to_nfa(regex: &str) -> Result<Nfa<u8>, Error> {
+    regex_to_nfa_limited(regex, &Limits::default())
+}
+
+/// [regex_to_nfa], failing when the links of the NFA go over `limits`
+pub fn regex_to_nfa_limited(regex: &str, limits: &Limits) -> Result<Nfa<u8>, Error> {
+    let mut parser = ParserBuilder::default().unicode(false).utf8(false).build();
+    hir_to_nfa_limited(&parser.parse(regex)?, limits)
+}
+
+pub fn hir_to_nfa(hir: &Hir) -> Result<Nfa<u8>, Error> {
+    hir_to_nfa_limited(hir, &Limits::default())
+}
+
+/// [hir_to_nfa], failing when the links of the NFA go over `limits`
+///
+/// The links are counted while the NFA is built, so that a large repetition fails before using
+/// all the memory.
+pub fn hir_to_nfa_limited(hir: &Hir, limits: &Limits) -> Result<Nfa<u8>, Error> {
     let mut nfa = Automata::new();
     let mut counter = Counter::new(0);
-    let (start, end) = rec_hir_to_nfa(hir, &mut counter, &mut nfa);
+    let (start, end) = rec_hir_to_nfa(hir, &mut counter, &mut nfa, limits)?;
     nfa.initial_states.insert(start);
     nfa.accept_states.insert(end);
-    nfa
+    Ok(nfa)
 }
 
-fn rec_hir_to_nfa(hir: &Hir, counter: &mut Counter, nfa: &mut Nfa<u8>) -> (State, State) {
+fn rec_hir_to_nfa(
+    hir: &Hir,
+    counter: &mut Counter,
+    nfa: &mut Nfa<u8>,
+    limits: &Limits,
+) -> Result<(State, State), Error> {
     let start = counter.next();
     let end = counter.next();
     match hir.kind() {
@@ -46,7 +75,7 @@ fn rec_hir_to_nfa(hir: &Hir, counter: &mut Counter, nfa: &mut Nfa<u8>) -> (State
                 let mut common_len = 0;
                 for (len, bytes) in iter {
                     assert!(prev_len <= 4);
-                    assert!(1 <= len && len <= 4);
+                    assert!((1..=4).contains(&len));
 
                     let mut prev_state = start;
                     for i in 0..len {
@@ -80,24 +109,26 @@ fn rec_hir_to_nfa(hir: &Hir, counter: &mut Counter, nfa: &mut Nfa<u8>) -> (State
                 }
             }
         },
-        HirKind::Look(_) => todo!(),
+        // anchors and word boundaries depend on the decoded text around the match
+        HirKind::Look(_) => return Err(Error::Unsupported("look-around assertion")),
         HirKind::Repetition(repetition) => {
             let mut prev = start;
             for _ in 0..repetition.min {
-                let (item_start, item_end) = rec_hir_to_nfa(&repetition.sub, counter, nfa);
+                let (item_start, item_end) = rec_hir_to_nfa(&repetition.sub, counter, nfa, limits)?;
                 nfa.link(prev, item_start, Epsilon);
                 prev = item_end;
             }
 
             if let Some(max) = repetition.max {
                 for _ in repetition.min..max {
-                    let (item_start, item_end) = rec_hir_to_nfa(&repetition.sub, counter, nfa);
+                    let (item_start, item_end) =
+                        rec_hir_to_nfa(&repetition.sub, counter, nfa, limits)?;
                     nfa.link(prev, item_start, Epsilon);
                     nfa.link(prev, end, Epsilon);
                     prev = item_end;
                 }
             } else {
-                let (item_start, item_end) = rec_hir_to_nfa(&repetition.sub, counter, nfa);
+                let (item_start, item_end) = rec_hir_to_nfa(&repetition.sub, counter, nfa, limits)?;
                 nfa.link(prev, item_start, Epsilon);
                 nfa.link(item_end, item_start, Epsilon);
                 nfa.link(item_end, end, Epsilon);
@@ -105,14 +136,14 @@ fn rec_hir_to_nfa(hir: &Hir, counter: &mut Counter, nfa: &mut Nfa<u8>) -> (State
             nfa.link(prev, end, Epsilon);
         }
         HirKind::Capture(capture) => {
-            let (item_start, item_end) = rec_hir_to_nfa(&capture.sub, counter, nfa);
+            let (item_start, item_end) = rec_hir_to_nfa(&capture.sub, counter, nfa, limits)?;
             nfa.link(start, item_start, Epsilon);
             nfa.link(item_end, end, Epsilon);
         }
         HirKind::Concat(list) => {
             let mut prev = start;
             for item in list {
-                let (item_start, item_end) = rec_hir_to_nfa(item, counter, nfa);
+                let (item_start, item_end) = rec_hir_to_nfa(item, counter, nfa, limits)?;
                 nfa.link(prev, item_start, Epsilon);
                 prev = item_end;
             }
@@ -120,11 +151,35 @@ fn rec_hir_to_nfa(hir: &Hir, counter: &mut Counter, nfa: &mut Nfa<u8>) -> (State
         }
         HirKind::Alternation(list) => {
             for item in list {
-                let (item_start, item_end) = rec_hir_to_nfa(item, counter, nfa);
+                let (item_start, item_end) = rec_hir_to_nfa(item, counter, nfa, limits)?;
                 nfa.link(start, item_start, Epsilon);
                 nfa.link(item_end, end, Epsilon);
             }
         }
     }
-    (start, end)
+    limits.check(Limit::Links, nfa.links.len())?;
+    Ok((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limited_while_building() {
+        let limits = Limits {
+            max_links: Some(1000),
+            ..Limits::default()
+        };
+        // 400k copies of `.` once built
+        let err = regex_to_nfa_limited(".{20000}{20}", &limits).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::LimitExceeded {
+                limit: Limit::Links,
+                max: 1000
+            }
+        ));
+        assert!(regex_to_nfa_limited("a{100}", &limits).is_ok());
+    }
 }
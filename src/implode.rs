@@ -1,54 +1,279 @@
-use std::{fmt::Debug, hash::Hash};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    hash::Hash,
+    rc::Rc,
+};
 
-use crate::automata::{Automata, Dfa, State};
+use crate::{
+    automata::{Automata, Dfa, State},
+    error::Error,
+    limits::{Limit, Limits},
+};
 
 pub fn implode_dfa<MF, T, T2>(dfa: &Dfa<T>, n: usize, merge_fn: MF) -> Dfa<T2>
 where
     MF: Fn(Vec<T>) -> Vec<T2>,
     T: Clone + Hash + Eq + Debug,
+    T2: Clone,
 {
+    implode_dfa_limited(dfa, n, merge_fn, &Limits::default()).expect("no limits")
+}
+
+/// [implode_dfa], failing when the paths to merge or the links go over `limits`
+///
+/// The DFA is walked as a product with the position in the group: only the states reached at
+/// position 0 (the initial states and the ends of the groups) start a group. The groups read
+/// from a state with `len` symbols left are built once per `(state, len)` from the groups of
+/// the next states, and their sequences of symbols are interned in a trie shared by every
+/// state. Each link of the DFA is read at most `n` times, `merge_fn` is called once per
+/// distinct sequence, and the groups built are at most `n` times the groups of the group
+/// starts, which can still be up to the alphabet to the power `n` per group start.
+pub fn implode_dfa_limited<MF, T, T2>(
+    dfa: &Dfa<T>,
+    n: usize,
+    merge_fn: MF,
+    limits: &Limits,
+) -> Result<Dfa<T2>, Error>
+where
+    MF: Fn(Vec<T>) -> Vec<T2>,
+    T: Clone + Hash + Eq + Debug,
+    T2: Clone,
+{
+    let mut links_from: HashMap<State, Vec<(&T, State)>> = HashMap::new();
+    for link in &dfa.links {
+        links_from
+            .entry(link.from)
+            .or_default()
+            .push((&link.symbol, link.to));
+    }
+    let mut groups = Groups {
+        links_from: &links_from,
+        accept_states: &dfa.accept_states,
+        merge_fn,
+        sequences: Vec::new(),
+        sequence_ids: HashMap::new(),
+        merged: HashMap::new(),
+        groups: HashMap::new(),
+    };
+
+    let mut paths = 0;
     let mut imploded = Automata::new();
     imploded.initial_states = dfa.initial_states.clone();
     imploded.accept_states = dfa.accept_states.clone();
 
-    for from in dfa.all_states() {
-        let mut implosions = Vec::new();
-        rec_implode(&mut implosions, &mut Vec::new(), dfa, from, n);
-
-        for (symbol_seq, to) in implosions {
-            // if the sequence is not full, the merge_fn should return every possible symbol
-            // when the mapping is not 1-to-1 (base64, hex, ...)
-            let imploded_symbols = merge_fn(symbol_seq);
+    let mut explored: HashSet<State> = dfa.initial_states.clone();
+    let mut to_explore: Vec<State> = dfa.initial_states.iter().copied().collect();
+    while let Some(from) = to_explore.pop() {
+        let from_groups = groups.groups(from, n);
+        paths += from_groups.len();
+        limits.check(Limit::ImplodePaths, paths)?;
 
-            for symbol in imploded_symbols {
-                imploded.link(from, to, symbol);
+        for (sequence, to) in from_groups.iter() {
+            if explored.insert(*to) {
+                to_explore.push(*to);
+            }
+            for symbol in groups.merged(*sequence).iter() {
+                imploded.link(from, *to, symbol.clone());
             }
         }
+        limits.check(Limit::Links, imploded.links.len())?;
     }
 
-    imploded
+    Ok(imploded)
 }
 
-fn rec_implode<T>(
-    implosions: &mut Vec<(Vec<T>, State)>,
-    curr_seq: &mut Vec<T>,
-    dfa: &Dfa<T>,
-    from_state: State,
-    n: usize,
-) where
-    T: Clone + Hash + Eq + Debug,
+/// Index of a sequence of symbols in [Groups::sequences]
+type SequenceId = usize;
+
+/// Sequences of symbols read from a state, with the state they end at
+type StateGroups = Rc<Vec<(SequenceId, State)>>;
+
+/// Groups of at most `n` symbols read from the states of a DFA, shared between the states
+struct Groups<'a, T, T2, MF> {
+    links_from: &'a HashMap<State, Vec<(&'a T, State)>>,
+    accept_states: &'a HashSet<State>,
+    merge_fn: MF,
+    /// Trie of the sequences met, from their end: each sequence is its first symbol followed
+    /// by a shorter sequence, if any
+    sequences: Vec<(&'a T, Option<SequenceId>)>,
+    sequence_ids: HashMap<(&'a T, Option<SequenceId>), SequenceId>,
+    /// `merge_fn` of the sequences already met, the same ones come back from most states
+    merged: HashMap<SequenceId, Rc<Vec<T2>>>,
+    /// Groups read from a state with at most `len` symbols, with the state they end at
+    groups: HashMap<(State, usize), StateGroups>,
+}
+
+impl<'a, T, T2, MF> Groups<'a, T, T2, MF>
+where
+    MF: Fn(Vec<T>) -> Vec<T2>,
+    T: Clone + Hash + Eq,
 {
-    for link in dfa.links_from(from_state) {
-        curr_seq.push(link.symbol.clone());
+    /// Groups read from `state` with at most `len` symbols: they stop at the accept states and
+    /// after `len` symbols
+    fn groups(&mut self, state: State, len: usize) -> StateGroups {
+        if let Some(groups) = self.groups.get(&(state, len)) {
+            return groups.clone();
+        }
+        let links_from = self.links_from;
+        let mut groups = Vec::new();
+        for (symbol, to) in links_from.get(&state).into_iter().flatten() {
+            if self.accept_states.contains(to) || len == 1 {
+                groups.push((self.sequence(symbol, None), *to));
+            }
+            if len > 1 {
+                for (rest, end) in self.groups(*to, len - 1).iter() {
+                    groups.push((self.sequence(symbol, Some(*rest)), *end));
+                }
+            }
+        }
+        let groups = Rc::new(groups);
+        self.groups.insert((state, len), groups.clone());
+        groups
+    }
+
+    fn sequence(&mut self, first: &'a T, rest: Option<SequenceId>) -> SequenceId {
+        let sequences = &mut self.sequences;
+        *self.sequence_ids.entry((first, rest)).or_insert_with(|| {
+            sequences.push((first, rest));
+            sequences.len() - 1
+        })
+    }
 
-        if dfa.accept_states.contains(&link.to) || n == 1 {
-            implosions.push((curr_seq.clone(), link.to));
+    // if the sequence is not full, the merge_fn should return every possible symbol when the
+    // mapping is not 1-to-1 (base64, hex, ...)
+    fn merged(&mut self, id: SequenceId) -> Rc<Vec<T2>> {
+        if let Some(merged) = self.merged.get(&id) {
+            return merged.clone();
         }
+        let mut symbols = Vec::new();
+        let mut next = Some(id);
+        while let Some(id) = next {
+            let (symbol, rest) = self.sequences[id];
+            symbols.push(symbol.clone());
+            next = rest;
+        }
+        let merged = Rc::new((self.merge_fn)(symbols));
+        self.merged.insert(id, merged.clone());
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::{determine::determine_and_min_nfa, explode::explode_dfa, hir_to_nfa::regex_to_nfa};
+
+    const PATTERNS: &[&str] = &[
+        "",
+        "a",
+        "ab*c",
+        "(a|b)*a(a|b){3}",
+        "[a-z0-9]{4,9}",
+        "(GET|POST) /[a-z/]{1,8}\\?id=[0-9]+",
+        "x(yz)*|[^x]{2}",
+    ];
+
+    fn dfa(regex: &str) -> Dfa<u8> {
+        determine_and_min_nfa(regex_to_nfa(regex).unwrap())
+    }
+
+    fn bits_dfa(regex: &str) -> Dfa<bool> {
+        let bits = |byte: &u8| (0..8).rev().map(|bit| byte >> bit & 1 == 1).collect();
+        determine_and_min_nfa(explode_dfa(&dfa(regex), bits).to_nfa())
+    }
+
+    /// Values of the groups of `n` bits starting with `bits`
+    fn group_values(n: usize, bits: Vec<bool>) -> Vec<u8> {
+        let prefix = bits
+            .iter()
+            .fold(0_u8, |value, bit| (value << 1) | *bit as u8);
+        let free = n - bits.len();
+        (0..1_u8 << free)
+            .map(|suffix| (prefix << free) | suffix)
+            .collect()
+    }
+
+    /// The first implosion: every path of up to `n` links from every state
+    fn rec_implode_dfa<T: Clone, T2>(
+        dfa: &Dfa<T>,
+        n: usize,
+        merge_fn: impl Fn(Vec<T>) -> Vec<T2>,
+    ) -> Dfa<T2> {
+        let mut imploded = Automata::new();
+        imploded.initial_states = dfa.initial_states.clone();
+        imploded.accept_states = dfa.accept_states.clone();
 
-        if n > 1 {
-            rec_implode(implosions, curr_seq, dfa, link.to, n - 1);
+        for from in dfa.all_states() {
+            let mut implosions = Vec::new();
+            rec_implode(&mut implosions, &mut Vec::new(), dfa, from, n);
+            for (symbol_seq, to) in implosions {
+                for symbol in merge_fn(symbol_seq) {
+                    imploded.link(from, to, symbol);
+                }
+            }
+        }
+
+        imploded
+    }
+
+    fn rec_implode<T: Clone>(
+        implosions: &mut Vec<(Vec<T>, State)>,
+        curr_seq: &mut Vec<T>,
+        dfa: &Dfa<T>,
+        from_state: State,
+        n: usize,
+    ) {
+        for link in dfa.links_from(from_state) {
+            curr_seq.push(link.symbol.clone());
+            if dfa.accept_states.contains(&link.to) || n == 1 {
+                implosions.push((curr_seq.clone(), link.to));
+            }
+            if n > 1 {
+                rec_implode(implosions, curr_seq, dfa, link.to, n - 1);
+            }
+            curr_seq.pop();
         }
+    }
+
+    #[test]
+    fn same_as_rec_implode() {
+        for pattern in PATTERNS {
+            let bits = bits_dfa(pattern);
+            for n in 1..=6 {
+                let merge_fn = |bits| group_values(n, bits);
+                let imploded = determine_and_min_nfa(implode_dfa(&bits, n, merge_fn).to_nfa());
+                let expected = determine_and_min_nfa(rec_implode_dfa(&bits, n, merge_fn).to_nfa());
+                assert!(imploded.equivalent(&expected), "`{pattern}`, {n} bits");
+            }
+
+            // the sequences themselves, whatever the alphabet
+            let bytes = dfa(pattern);
+            for n in 1..=2 {
+                let imploded =
+                    determine_and_min_nfa(implode_dfa(&bytes, n, |seq| vec![seq]).to_nfa());
+                let expected =
+                    determine_and_min_nfa(rec_implode_dfa(&bytes, n, |seq| vec![seq]).to_nfa());
+                assert!(imploded.equivalent(&expected), "`{pattern}`, {n} bytes");
+            }
+        }
+    }
 
-        curr_seq.pop();
+    #[test]
+    fn merges_each_sequence_once() {
+        let bits = bits_dfa("(GET|POST) /[a-z/]{1,8}\\?id=[0-9]+");
+        let calls = RefCell::new(Vec::new());
+        implode_dfa(&bits, 6, |seq: Vec<bool>| {
+            calls.borrow_mut().push(seq.clone());
+            group_values(6, seq)
+        });
+        let calls = calls.into_inner();
+        assert_eq!(calls.iter().unique().count(), calls.len());
+        // at most the sequences of 1 to 6 bits
+        assert!(calls.len() <= 126);
     }
 }
@@ -0,0 +1,240 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::Debug,
+    hash::Hash,
+};
+
+use itertools::Itertools;
+
+use crate::{
+    automata::{Automata, Dfa, MaybeSymbol, Nfa, State},
+    determine::determine_nfa_states,
+    error::Error,
+    explode::explode_dfa,
+    implode::implode_dfa,
+    limits::Limits,
+};
+
+/// Index of a pattern in the list given to [Labelled::union]
+pub type PatternId = usize;
+
+/// Automata whose accept states are labelled with the patterns they accept, to convert several
+/// patterns at once and still know which one matched
+#[derive(Debug, Clone)]
+pub struct Labelled<T> {
+    pub automata: Automata<T>,
+    /// Patterns accepted by each accept state
+    pub labels: HashMap<State, BTreeSet<PatternId>>,
+}
+
+impl<T> Labelled<MaybeSymbol<T>> {
+    /// NFA accepting the strings of every pattern, labelled with its index
+    pub fn union(nfas: impl IntoIterator<Item = Nfa<T>>) -> Self {
+        let mut labels: HashMap<State, BTreeSet<PatternId>> = HashMap::new();
+        let mut offset = 0;
+
+        let nfas = nfas.into_iter().collect_vec();
+        for (id, nfa) in nfas.iter().enumerate() {
+            for accept in &nfa.accept_states {
+                labels.entry(accept + offset).or_default().insert(id);
+            }
+            offset += nfa.max_state() + 1;
+        }
+
+        Self {
+            automata: Automata::union(nfas),
+            labels,
+        }
+    }
+
+    /// Same as [determine_and_min_nfa](crate::determine::determine_and_min_nfa), without
+    /// merging states accepting different patterns
+    pub fn determine_and_min(&self) -> Labelled<T>
+    where
+        T: Ord + Hash + Clone + Debug,
+    {
+        self.determine_and_min_limited(&Limits::default())
+            .expect("no limits")
+    }
+
+    /// [Labelled::determine_and_min], failing when the DFA goes over `limits`
+    pub fn determine_and_min_limited(&self, limits: &Limits) -> Result<Labelled<T>, Error>
+    where
+        T: Ord + Hash + Clone + Debug,
+    {
+        let (automata, states) = determine_nfa_states(&self.automata, limits)?;
+
+        let mut labels: HashMap<State, BTreeSet<PatternId>> = HashMap::new();
+        for (multi_state, state) in states {
+            for nfa_state in multi_state {
+                labels
+                    .entry(state)
+                    .or_default()
+                    .extend(self.labels.get(&nfa_state).into_iter().flatten());
+            }
+        }
+        labels.retain(|_, patterns| !patterns.is_empty());
+
+        let mut dfa = Labelled { automata, labels };
+        dfa.automata.trim();
+        Ok(dfa.minimize())
+    }
+}
+
+impl<T> Labelled<T> {
+    /// Merge the states of a DFA accepting the same strings for every pattern (Moore's
+    /// algorithm)
+    pub fn minimize(&self) -> Self
+    where
+        T: Ord + Hash + Clone,
+    {
+        let links_from = self
+            .automata
+            .links
+            .iter()
+            .map(|link| (link.from, link))
+            .into_group_map();
+        let states = self
+            .automata
+            .all_states()
+            .into_iter()
+            .sorted()
+            .collect_vec();
+
+        // start from the states accepting the same patterns, split the classes whose states
+        // lead to different classes
+        let mut classes = number_by(&states, |state| {
+            let accept = self.automata.accept_states.contains(state);
+            (accept, self.labels.get(state))
+        });
+        loop {
+            let refined = number_by(&states, |state| {
+                let next_classes = links_from
+                    .get(state)
+                    .into_iter()
+                    .flatten()
+                    .map(|link| (link.symbol.clone(), classes[&link.to]))
+                    .sorted()
+                    .collect_vec();
+                (classes[state], next_classes)
+            });
+            let count = |classes: &HashMap<State, State>| classes.values().unique().count();
+            let stable = count(&refined) == count(&classes);
+            classes = refined;
+            if stable {
+                break;
+            }
+        }
+
+        let mut minimized = Automata::new();
+        minimized.initial_states = self
+            .automata
+            .initial_states
+            .iter()
+            .map(|state| classes[state])
+            .collect();
+        minimized.accept_states = self
+            .automata
+            .accept_states
+            .iter()
+            .map(|state| classes[state])
+            .collect();
+        let links: HashSet<_> = self
+            .automata
+            .links
+            .iter()
+            .map(|link| (classes[&link.from], link.symbol.clone(), classes[&link.to]))
+            .collect();
+        for (from, symbol, to) in links {
+            minimized.link(from, to, symbol);
+        }
+
+        let labels = self
+            .labels
+            .iter()
+            .filter_map(|(state, patterns)| Some((*classes.get(state)?, patterns.clone())))
+            .collect();
+
+        Labelled {
+            automata: minimized,
+            labels,
+        }
+    }
+
+    /// [Dfa::with_any_prefix], keeping the labels
+    pub fn with_any_prefix(&self, alphabet: &[T], max_len: usize) -> Labelled<MaybeSymbol<T>>
+    where
+        T: Clone,
+    {
+        Labelled {
+            automata: self.automata.with_any_prefix(alphabet, max_len),
+            labels: self.labels.clone(),
+        }
+    }
+
+    /// [explode_dfa], keeping the labels
+    pub fn explode<EF, T2>(&self, expand_fn: EF) -> Labelled<T2>
+    where
+        EF: Fn(&T) -> Vec<T2>,
+    {
+        Labelled {
+            automata: explode_dfa(&self.automata, expand_fn),
+            labels: self.labels.clone(),
+        }
+    }
+
+    /// [implode_dfa], keeping the labels
+    pub fn implode<MF, T2>(&self, n: usize, merge_fn: MF) -> Labelled<T2>
+    where
+        MF: Fn(Vec<T>) -> Vec<T2>,
+        T: Clone + Hash + Eq + Debug,
+        T2: Clone,
+    {
+        Labelled {
+            automata: implode_dfa(&self.automata, n, merge_fn),
+            labels: self.labels.clone(),
+        }
+    }
+
+    pub fn to_nfa(&self) -> Labelled<MaybeSymbol<T>>
+    where
+        T: Clone,
+    {
+        Labelled {
+            automata: self.automata.to_nfa(),
+            labels: self.labels.clone(),
+        }
+    }
+
+    /// Patterns matched when reaching `state`
+    pub fn matched_patterns(&self, state: State) -> impl Iterator<Item = PatternId> + '_ {
+        self.labels.get(&state).into_iter().flatten().copied()
+    }
+
+    /// Automata only accepting the strings of the pattern `id`
+    pub fn pattern_dfa(&self, id: PatternId) -> Dfa<T>
+    where
+        T: Clone,
+    {
+        let mut dfa = self.automata.clone();
+        dfa.accept_states
+            .retain(|state| self.matched_patterns(*state).contains(&id));
+        dfa.trim();
+        dfa
+    }
+}
+
+/// Number the states by their key, in order of appearance
+fn number_by<K: Eq + Hash>(
+    states: &[State],
+    key_fn: impl Fn(&State) -> K,
+) -> HashMap<State, State> {
+    let mut numbers = HashMap::new();
+    states
+        .iter()
+        .map(|state| {
+            let len = numbers.len() as State;
+            (*state, *numbers.entry(key_fn(state)).or_insert(len))
+        })
+        .collect()
+}
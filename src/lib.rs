@@ -1,8 +1,31 @@
 pub mod automata;
+pub mod base85;
+pub mod bit_groups;
+pub mod cache;
+pub mod case;
 pub mod counter;
 pub mod determine;
 pub mod dfa_to_hir;
+pub mod entity;
+pub mod error;
+pub mod escape;
 pub mod explode;
 pub mod hir_to_nfa;
 pub mod implode;
+pub mod json;
+pub mod labelled;
+pub mod limits;
+pub mod literals;
+pub mod percent;
+pub mod pipeline;
+pub mod quoted_printable;
+pub mod rule;
+pub mod to_binary;
 pub mod to_dot;
+pub mod to_json;
+pub mod to_mermaid;
+pub mod to_regex;
+pub mod trace;
+pub mod transducer;
+pub mod utf16;
+pub mod xor;
@@ -0,0 +1,104 @@
+use std::{
+    fmt::{self, Display},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::{automata::Automata, error::Error};
+
+/// Quantity bounded by [Limits]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// States of a DFA being determinized, including the reversed DFA of the minimization
+    DfaStates,
+    /// Links of an automaton
+    Links,
+    /// Length of the output regex
+    RegexLen,
+    /// Paths of symbols merged by [implode_dfa](crate::implode::implode_dfa)
+    ImplodePaths,
+    /// Links of the digits of a group of [base85_dfa](crate::base85::base85_dfa)
+    GroupLinks,
+}
+
+impl Limit {
+    pub const ALL: [Limit; 5] = [
+        Limit::DfaStates,
+        Limit::Links,
+        Limit::RegexLen,
+        Limit::ImplodePaths,
+        Limit::GroupLinks,
+    ];
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Limit::DfaStates => "DFA states",
+            Limit::Links => "links",
+            Limit::RegexLen => "output regex length",
+            Limit::ImplodePaths => "implode paths",
+            Limit::GroupLinks => "Base85 group links",
+        })
+    }
+}
+
+/// Bounds on the size of the automata built by a conversion, to fail early on patterns whose
+/// encoding explodes instead of using all the memory
+///
+/// `None` is unbounded, the default.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    pub max_dfa_states: Option<usize>,
+    pub max_links: Option<usize>,
+    pub max_regex_len: Option<usize>,
+    pub max_implode_paths: Option<usize>,
+    pub max_group_links: Option<usize>,
+    /// Where to record the values checked, see [Peaks]
+    pub peaks: Option<Arc<Peaks>>,
+}
+
+/// Largest value checked against each [Limit]
+///
+/// The steps of a conversion don't depend on the limits, so a conversion that recorded these
+/// peaks passes any limits at least as large, and fails the others.
+#[derive(Debug, Default)]
+pub struct Peaks([AtomicUsize; Limit::ALL.len()]);
+
+impl Peaks {
+    pub fn get(&self, limit: Limit) -> usize {
+        self.0[limit as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn record(&self, limit: Limit, value: usize) {
+        self.0[limit as usize].fetch_max(value, Ordering::Relaxed);
+    }
+}
+
+impl Limits {
+    /// Fail with [Error::LimitExceeded] when `value` is over the bound of `limit`
+    pub fn check(&self, limit: Limit, value: usize) -> Result<(), Error> {
+        if let Some(peaks) = &self.peaks {
+            peaks.record(limit, value);
+        }
+        let max = match limit {
+            Limit::DfaStates => self.max_dfa_states,
+            Limit::Links => self.max_links,
+            Limit::RegexLen => self.max_regex_len,
+            Limit::ImplodePaths => self.max_implode_paths,
+            Limit::GroupLinks => self.max_group_links,
+        };
+        match max {
+            Some(max) if value > max => Err(Error::LimitExceeded { limit, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the states and links of an automaton built by a step without limits
+    pub fn check_automata<T>(&self, automata: &Automata<T>) -> Result<(), Error> {
+        self.check(Limit::Links, automata.links.len())?;
+        self.check(Limit::DfaStates, automata.all_states().len())
+    }
+}
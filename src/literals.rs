@@ -0,0 +1,226 @@
+use std::{collections::HashSet, hash::Hash};
+
+use itertools::Itertools;
+
+use crate::automata::{Dfa, State};
+
+/// Literals that every string accepted by the DFA contains, longest first
+///
+/// Literals are read around the states that every accepting path goes through, as far as all the
+/// paths agree on the symbols. Literals shorter than `min_len` and literals contained in a longer
+/// one are dropped.
+pub fn required_literals<T>(dfa: &Dfa<T>, min_len: usize) -> Vec<Vec<T>>
+where
+    T: Clone + Eq + Hash,
+{
+    let mut dfa = dfa.clone();
+    dfa.trim();
+
+    let mandatory: HashSet<State> = dfa
+        .all_states()
+        .into_iter()
+        .filter(|state| !reaches_accept_avoiding(&dfa, &HashSet::from([*state])))
+        .collect();
+
+    let runs = mandatory
+        .iter()
+        .map(|state| literal_around(&dfa, *state))
+        .filter(|run| run.len() >= min_len.max(1))
+        .unique()
+        .sorted_by_key(|run| std::cmp::Reverse(run.len()))
+        .collect_vec();
+
+    runs.iter()
+        .enumerate()
+        .filter(|(idx, run)| !runs[..*idx].iter().any(|longer| contains(longer, run)))
+        .map(|(_, run)| run.clone())
+        .collect()
+}
+
+/// Literals such that every string accepted by the DFA contains at least one of them
+///
+/// Useful when [required_literals] finds nothing, e.g. when the pattern is split between several
+/// alternatives. Returns `None` when no such set made of literals of at least `min_len` symbols
+/// exists, or when it needs more than `max_count` literals.
+pub fn alternative_literals<T>(
+    dfa: &Dfa<T>,
+    min_len: usize,
+    max_count: usize,
+) -> Option<Vec<Vec<T>>>
+where
+    T: Clone + Eq + Hash,
+{
+    let mut dfa = dfa.clone();
+    dfa.trim();
+
+    if dfa.accept_states.is_empty() {
+        return None;
+    }
+
+    // every accepting path going through one of these states reads its literal
+    let candidates = dfa
+        .all_states()
+        .into_iter()
+        .map(|state| (state, literal_around(&dfa, state)))
+        .filter(|(_, run)| run.len() >= min_len.max(1))
+        .sorted_by_key(|(state, run)| (run.len(), *state))
+        .collect_vec();
+
+    let mut cut: HashSet<State> = candidates.iter().map(|(state, _)| *state).collect();
+    if reaches_accept_avoiding(&dfa, &cut) {
+        return None;
+    }
+
+    // keep the longest (most selective) literals: try to drop the shortest ones first
+    for (state, _) in &candidates {
+        cut.remove(state);
+        if reaches_accept_avoiding(&dfa, &cut) {
+            cut.insert(*state);
+        }
+    }
+
+    let literals = candidates
+        .into_iter()
+        .filter(|(state, _)| cut.contains(state))
+        .map(|(_, run)| run)
+        .unique()
+        .sorted_by_key(|run| std::cmp::Reverse(run.len()))
+        .collect_vec();
+
+    (literals.len() <= max_count).then_some(literals)
+}
+
+/// An accept state can be reached from an initial state without going through `avoid`
+fn reaches_accept_avoiding<T>(dfa: &Dfa<T>, avoid: &HashSet<State>) -> bool {
+    let mut explored: HashSet<State> = dfa
+        .initial_states
+        .iter()
+        .copied()
+        .filter(|initial| !avoid.contains(initial))
+        .collect();
+    let mut to_explore = explored.clone();
+
+    while !to_explore.is_empty() {
+        for from in std::mem::take(&mut to_explore) {
+            for link in dfa.links_from(from) {
+                if !avoid.contains(&link.to) && explored.insert(link.to) {
+                    to_explore.insert(link.to);
+                }
+            }
+        }
+    }
+
+    dfa.accept_states
+        .iter()
+        .any(|accept| explored.contains(accept))
+}
+
+/// Symbols read by every accepting path right before and right after going through `state`
+///
+/// The DFA must be trimmed: walking backward (resp. forward) then always ends on an initial
+/// (resp. accept) state.
+fn literal_around<T: Clone + Eq>(dfa: &Dfa<T>, state: State) -> Vec<T> {
+    let mut literal = Vec::new();
+
+    let mut states = HashSet::from([state]);
+    while !states
+        .iter()
+        .any(|state| dfa.initial_states.contains(state))
+    {
+        let incomings = states.iter().flat_map(|to| dfa.links_to(*to)).collect_vec();
+        let Ok(symbol) = incomings.iter().map(|link| &link.symbol).all_equal_value() else {
+            break;
+        };
+        literal.push(symbol.clone());
+        states = incomings.iter().map(|link| link.from).collect();
+    }
+    literal.reverse();
+
+    let mut states = HashSet::from([state]);
+    while !states.iter().any(|state| dfa.accept_states.contains(state)) {
+        let outgoings = states
+            .iter()
+            .flat_map(|from| dfa.links_from(*from))
+            .collect_vec();
+        let Ok(symbol) = outgoings.iter().map(|link| &link.symbol).all_equal_value() else {
+            break;
+        };
+        literal.push(symbol.clone());
+        states = outgoings.iter().map(|link| link.to).collect();
+    }
+
+    literal
+}
+
+fn contains<T: Eq>(haystack: &[T], needle: &[T]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{determine::determine_and_min_nfa, hir_to_nfa::regex_to_nfa};
+
+    fn dfa(regex: &str) -> Dfa<u8> {
+        determine_and_min_nfa(regex_to_nfa(regex).unwrap())
+    }
+
+    fn strings(literals: Vec<Vec<u8>>) -> Vec<String> {
+        literals
+            .into_iter()
+            .map(|literal| String::from_utf8(literal).unwrap())
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn required() {
+        let cases: &[(&str, usize, &[&str])] = &[
+            ("abc[0-9]+def", 3, &["abc", "def"]),
+            ("foo(bar|baz)qux", 1, &["fooba", "qux"]),
+            ("ab[0-9]cdef", 2, &["ab", "cdef"]),
+            ("ab[0-9]cdef", 3, &["cdef"]),
+            ("x*abcd", 1, &["abcd"]),
+        ];
+        for (regex, min_len, expected) in cases {
+            let literals = strings(required_literals(&dfa(regex), *min_len));
+            assert_eq!(literals, *expected, "{regex}");
+        }
+    }
+
+    #[test]
+    fn longest_first() {
+        let literals = required_literals(&dfa("ab[0-9]cdef"), 1);
+        assert_eq!(literals, vec![b"cdef".to_vec(), b"ab".to_vec()]);
+    }
+
+    #[test]
+    fn no_required_literal() {
+        for regex in ["[a-z]+", "ab|cd", "a?b?", "[^x]"] {
+            assert!(required_literals(&dfa(regex), 1).is_empty(), "{regex}");
+        }
+        // the literals are too short
+        assert!(required_literals(&dfa("a[0-9]b"), 2).is_empty());
+    }
+
+    #[test]
+    fn alternatives() {
+        let literals = alternative_literals(&dfa("xab|ycd"), 2, 4).unwrap();
+        assert_eq!(strings(literals), ["xab", "ycd"]);
+        let literals = alternative_literals(&dfa("(foo|bar)[0-9]+"), 3, 4).unwrap();
+        assert_eq!(strings(literals), ["bar", "foo"]);
+    }
+
+    #[test]
+    fn no_alternative_literals() {
+        // some strings contain no literal
+        assert_eq!(alternative_literals(&dfa("[a-z]+"), 1, 4), None);
+        assert_eq!(alternative_literals(&dfa("abc|[0-9]"), 2, 4), None);
+        // too many literals
+        assert_eq!(alternative_literals(&dfa("ab|cd|ef"), 2, 2), None);
+        // nothing is accepted
+        assert_eq!(alternative_literals(&Dfa::<u8>::new(), 1, 4), None);
+    }
+}
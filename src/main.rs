@@ -1,98 +1,911 @@
-use clap::Parser;
+use std::{
+    fmt::{Debug, Display},
+    fs,
+    hash::Hash,
+    io::{self, stdin},
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
+
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
 use itertools::Itertools;
 use regex_conv::{
-    determine::determine_and_min_nfa, dfa_to_hir::dfa_to_hir, explode::explode_dfa,
-    hir_to_nfa::hir_to_nfa, implode::implode_dfa, to_dot::automata_to_dot,
-};
-use regex_syntax::{
-    hir::{Class, ClassBytes, ClassBytesRange, Hir},
-    ParserBuilder,
+    automata::Dfa,
+    base85::{Base85Alphabet, OverGroupLinks},
+    bit_groups::{bit_groups_labelled_limited, BitGroupEncoding},
+    cache::Cache,
+    case::case_insensitive_dfa_limited,
+    determine::determine_and_min_nfa_limited,
+    dfa_to_hir::{dfa_to_hir, HirOptions, HirSymbol},
+    entity::{entity_encode_dfa_limited, EntityFlavor},
+    error::Error,
+    escape::{escape_dfa_limited, EscapeScheme},
+    explode::explode_dfa,
+    hir_to_nfa::regex_to_nfa_limited,
+    json::write_json_string,
+    labelled::{Labelled, PatternId},
+    limits::{Limit, Limits, Peaks},
+    percent::{percent_encode_dfa_limited, PercentOptions},
+    pipeline::{Pipeline, Stage},
+    quoted_printable::quoted_printable_dfa_limited,
+    rule::{content_literals, write_rule, ContentSymbol, Rule, RuleFlavor},
+    to_binary::{
+        automata_from_binary, automata_to_binary, read_varint, write_varint, FORMAT_VERSION,
+    },
+    to_dot::{DotOptions, RankDir},
+    to_regex::{hir_to_portable, hir_to_rust},
+    trace::{GraphFormat, GraphTrace},
+    utf16::{utf16_dfa_limited, Endianness},
+    xor::{xor_any_dfa_limited, xor_dfa, xor_repeating_dfa_limited},
 };
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
+    /// Match the ASCII letters of the pattern in both cases, like `(?i)`
+    #[arg(short, long)]
+    ignore_case: bool,
+    /// Encode the pattern as UTF-16 ("wide" strings) before the final encoding
+    #[arg(long, value_enum)]
+    utf16: Option<Utf16>,
+    /// XOR the pattern with a single byte key: `0x5a`, a list of candidate keys `1,2,0x10` or
+    /// `any` when the key is unknown
+    #[arg(long, value_parser = parse_xor_keys)]
+    xor: Option<XorKeys>,
+    /// XOR the pattern with a repeating multi-byte key written in hex (`DEADBEEF`), starting at
+    /// any position of the key. Usually combined with `--encoding raw`
+    #[arg(long, value_parser = parse_hex_key, conflicts_with = "xor")]
+    xor_key: Option<HexKey>,
+    /// Match the pattern in a string literal where bytes can be escaped (`\x41`, `\101`...)
+    #[arg(long, value_enum)]
+    escape: Option<Escape>,
+    /// Match the pattern with HTML (or XML) character references: `&lt;`, `&#60`, `&#x3C;`...
+    #[arg(long, value_enum)]
+    entities: Option<Entities>,
+    /// URL encode the pattern: every byte can be literal or `%XX`
+    #[arg(long)]
+    url_encode: bool,
+    /// With `--url-encode`, also match double encoded bytes (`%252F`)
+    #[arg(long, requires = "url_encode")]
+    url_double_encode: bool,
+    /// With `--url-encode`, also match `+` for spaces
+    #[arg(long, requires = "url_encode")]
+    url_plus_space: bool,
+    /// Quoted-printable encode the pattern (emails): every byte can be literal or `=XX`, with
+    /// optional soft line breaks
+    #[arg(long)]
+    quoted_printable: bool,
+    /// Final encoding of the pattern [default: base64]
+    #[arg(long, value_enum)]
+    encoding: Option<Encoding>,
+    /// Chain of encodings applied in order instead of `--encoding`, e.g. `utf16le,base64` for
+    /// the Base64 of UTF-16LE text. Stages: base64, base32, hex, ascii85, z85, utf16le, utf16be,
+    /// url, quoted-printable, c, json, js, python, html, xml
+    #[arg(long, value_delimiter = ',', conflicts_with = "encoding")]
+    pipeline: Option<Vec<Stage>>,
+    /// Only match encoded content starting at the beginning of a group of bytes (an offset
+    /// multiple of 3 for Base64)
     #[arg(long)]
     strict_offset: bool,
-    regex: String,
+    /// Emit greedy repetitions (`*`) instead of lazy ones (`*?`)
+    #[arg(long)]
+    greedy: bool,
+    /// Fold repeated sub-expressions into `+`, `?` and `{n,m}`
+    #[arg(long)]
+    counted_repetitions: bool,
+    /// Syntax of the output regex
+    #[arg(long, value_enum, default_value_t = Syntax::Rust)]
+    syntax: Syntax,
+    /// Output the bare regex or a full IDS rule
+    #[arg(long, value_enum, default_value_t = Format::Regex)]
+    format: Format,
+    /// `msg` of the generated rule (defaults to the input regex)
+    #[arg(long)]
+    msg: Option<String>,
+    /// `sid` of the generated rule
+    #[arg(long, default_value_t = 1000000)]
+    sid: u32,
+    /// Header of the generated rule
+    #[arg(long, default_value = "alert tcp any any -> any any")]
+    rule_header: String,
+    /// Convert every pattern of this file (one per line) at once, outputting one regex per
+    /// pattern. Only the Base64, Base32, hex and raw encodings are supported
+    #[arg(
+        long,
+        conflicts_with_all = ["ignore_case", "utf16", "xor", "xor_key", "escape", "entities", "url_encode", "quoted_printable", "pipeline"]
+    )]
+    patterns: Option<PathBuf>,
+    /// Convert each line of this file (`-` for stdin), `regex` or `id<TAB>regex`, on its own
+    /// and output JSON lines with the result or error of each. A line is split at its first tab,
+    /// so a regex with a literal tab needs an id (or `\t`)
+    #[arg(long, conflicts_with = "patterns")]
+    batch: Option<PathBuf>,
+    /// Write the automata built along the conversion to this directory, as numbered files
+    /// (`01-nfa.dot`, `02-dfa.dot`... the last one being the encoded DFA)
+    #[arg(long, conflicts_with_all = ["patterns", "batch"])]
+    dump_dot: Option<PathBuf>,
+    /// Fail when a DFA has more states, including the intermediate ones
+    #[arg(long)]
+    max_dfa_states: Option<usize>,
+    /// Fail when an automaton has more links
+    #[arg(long)]
+    max_links: Option<usize>,
+    /// Fail when the output regex is longer
+    #[arg(long)]
+    max_regex_len: Option<usize>,
+    /// Fail when the encoding merges more paths of bits into digits
+    #[arg(long)]
+    max_implode_paths: Option<usize>,
+    /// Fail when the digits of a group of Ascii85 or Z85 take more links. Groups only
+    /// constraining their last bytes are expensive to write exactly
+    #[arg(long, default_value_t = 16384)]
+    max_group_links: usize,
+    /// Instead of failing, widen the last constrained bytes of the Ascii85 or Z85 groups going
+    /// over `--max-group-links` to any byte: the regex then also matches strings that don't
+    /// encode the pattern
+    #[arg(long)]
+    widen_base85_groups: bool,
+    /// Reuse the encoded DFA of previous runs converting the same pattern with the same options,
+    /// stored in this directory
+    #[arg(long, conflicts_with = "patterns")]
+    cache_dir: Option<PathBuf>,
+    /// Language of the `--dump-dot` files
+    #[arg(long, value_enum, default_value_t = DumpFormat::Dot, requires = "dump_dot")]
+    dump_format: DumpFormat,
+    /// Layout direction of the `--dump-dot` graphs
+    #[arg(long, value_enum, default_value_t = Rankdir::Lr, requires = "dump_dot")]
+    rankdir: Rankdir,
+    #[arg(
+        required_unless_present_any = ["patterns", "batch"],
+        conflicts_with_all = ["patterns", "batch"]
+    )]
+    regex: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Utf16 {
+    Le,
+    Be,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Escape {
+    C,
+    Json,
+    Js,
+    Python,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Entities {
+    Html,
+    Xml,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum Encoding {
+    Base64,
+    /// RFC 4648 Base32
+    Base32,
+    /// Hex digits of any case
+    Hex,
+    /// Adobe Ascii85 (PDF streams, btoa)
+    Ascii85,
+    /// ZeroMQ Z85
+    Z85,
+    /// No encoding, the output is a byte regex
+    Raw,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DumpFormat {
+    /// Graphviz
+    Dot,
+    /// Mermaid state diagram
+    Mermaid,
+    /// `{"states", "initial", "accept", "links"}` document
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Rankdir {
+    /// Left to right
+    Lr,
+    /// Top to bottom
+    Tb,
+    /// Right to left
+    Rl,
+    /// Bottom to top
+    Bt,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Syntax {
+    /// `regex` crate syntax
+    Rust,
+    /// Plain groups and `\xNN` escapes, for PCRE and YARA
+    Portable,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Regex,
+    Suricata,
+    Snort,
+}
+
+/// Shorter literals are not selective enough to be worth a `content` keyword
+const MIN_CONTENT_LEN: usize = 4;
+
 fn main() {
     let cli = Cli::parse();
 
-    let mut parser = ParserBuilder::default().unicode(false).utf8(false).build();
-    let mut hir = parser.parse(&cli.regex).unwrap();
+    let base85 = stages(&cli)
+        .iter()
+        .any(|stage| matches!(stage, Stage::Base85(..)));
+    if cli.widen_base85_groups && base85 {
+        eprintln!(
+            "warning: the Ascii85/Z85 groups whose digits take more than {} links are widened, \
+             the regex can match strings that don't encode the pattern",
+            cli.max_group_links
+        );
+    }
+
+    if let Some(path) = &cli.batch {
+        convert_batch(&cli, path);
+        return;
+    }
+    if let Some(path) = &cli.patterns {
+        convert_patterns(&cli, path);
+        return;
+    }
+    let regex = cli.regex.as_deref().unwrap();
+
+    let dot_options = DotOptions {
+        rankdir: match cli.rankdir {
+            Rankdir::Lr => RankDir::LeftRight,
+            Rankdir::Tb => RankDir::TopBottom,
+            Rankdir::Rl => RankDir::RightLeft,
+            Rankdir::Bt => RankDir::BottomTop,
+        },
+    };
+    let format = match cli.dump_format {
+        DumpFormat::Dot => GraphFormat::Dot,
+        DumpFormat::Mermaid => GraphFormat::Mermaid,
+        DumpFormat::Json => GraphFormat::Json,
+    };
+    let mut trace = cli
+        .dump_dot
+        .as_ref()
+        .map(|_| GraphTrace::new(format, dot_options));
+    let conversion = match trace.as_mut() {
+        Some(trace) => convert(&cli, regex, Some(trace)),
+        None => convert_cached(&cli, open_cache(&cli).as_ref(), regex),
+    }
+    .unwrap_or_else(|err| exit_with(ErrorKind::InvalidValue, err));
+
+    if let (Some(dir), Some(mut trace)) = (&cli.dump_dot, trace) {
+        match as_text(&conversion.dfa) {
+            Some(text) => trace.push("final", &text),
+            None => trace.push("final", &conversion.dfa),
+        }
+        write_trace(dir, &trace)
+            .unwrap_or_else(|err| exit_with(ErrorKind::Io, format!("{}: {err}", dir.display())));
+    }
+
+    let output = render_bytes(&cli, regex, cli.sid, &conversion.dfa)
+        .unwrap_or_else(|err| exit_with(ErrorKind::InvalidValue, err));
+    println!("{output}");
+}
 
-    if !cli.strict_offset {
-        let dot = Hir::class(Class::Bytes(ClassBytes::new([ClassBytesRange::new(
-            0, 255,
-        )])));
-        let dot_opt = Hir::alternation(vec![dot, Hir::empty()]);
-        hir = Hir::concat(vec![dot_opt.clone(), dot_opt.clone(), hir]);
+/// One file per automaton of the trace, numbered in order
+fn write_trace(dir: &Path, trace: &GraphTrace) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let extension = trace.format.extension();
+    for (idx, (name, graph)) in trace.graphs.iter().enumerate() {
+        fs::write(
+            dir.join(format!("{:02}-{name}.{extension}", idx + 1)),
+            graph,
+        )?;
     }
+    Ok(())
+}
 
-    let nfa = hir_to_nfa(&hir);
-    // automata_to_dot(&mut stdout(), &nfa).unwrap();
+/// Encoded DFA of a pattern, with the size of the automata along the way
+struct Conversion {
+    dfa: Dfa<u8>,
+    nfa_states: usize,
+    dfa_states: usize,
+    /// Largest value checked against each of [Limit::ALL], to check a cached result against
+    /// the limits of another run
+    peaks: [usize; Limit::ALL.len()],
+}
 
-    let dfa = determine_and_min_nfa(nfa);
+impl Conversion {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, self.nfa_states as u64);
+        write_varint(&mut bytes, self.dfa_states as u64);
+        for peak in self.peaks {
+            write_varint(&mut bytes, peak as u64);
+        }
+        automata_to_binary(&mut bytes, &self.dfa).unwrap();
+        bytes
+    }
 
-    // automata_to_dot(&mut stdout(), &dfa).unwrap();
+    fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let nfa_states = read_varint(&mut bytes)? as usize;
+        let dfa_states = read_varint(&mut bytes)? as usize;
+        let mut peaks = [0; Limit::ALL.len()];
+        for peak in &mut peaks {
+            *peak = read_varint(&mut bytes)? as usize;
+        }
+        Some(Self {
+            dfa: automata_from_binary(bytes).ok()?,
+            nfa_states,
+            dfa_states,
+            peaks,
+        })
+    }
+}
 
-    // dbg!(hir);
-    // dbg!(&nfa);
-    //nfa_to_dot(&mut stdout(), &nfa).unwrap();
-    let exploded = explode_dfa(&dfa, |byte: &u8| {
-        (0..8).rev().map(|i| (byte >> i) & 1 == 1).collect_vec()
-    });
+fn open_cache(cli: &Cli) -> Option<Cache> {
+    let dir = cli.cache_dir.as_ref()?;
+    let cache = Cache::new(dir)
+        .unwrap_or_else(|err| exit_with(ErrorKind::Io, format!("{}: {err}", dir.display())));
+    Some(cache)
+}
 
-    let min_exploded = determine_and_min_nfa(exploded.to_nfa());
+/// Revision of the results of [convert], part of the cache keys: bump it when a change makes the
+/// same pattern and options give another DFA or changes the layout of [Conversion], the version
+/// of the crate alone doesn't change between releases
+const CONVERSION_REVISION: u32 = 3;
 
-    // automata_to_dot(&mut stdout(), &min_exploded).unwrap();
+/// [convert], reusing the result of a previous run with the same pattern and options
+///
+/// The limits are not part of the key: the peaks of a cached result are checked against the
+/// current limits, it passes or fails like the conversion would.
+fn convert_cached(cli: &Cli, cache: Option<&Cache>, regex: &str) -> Result<Conversion, Error> {
+    let Some(cache) = cache else {
+        return convert(cli, regex, None);
+    };
 
-    let imploded = implode_dfa(&min_exploded, 6, |list| {
-        assert!(list.len() <= 6);
-        if list.len() == 0 {
-            return Vec::new();
+    // the output regex options only change how the DFA is written
+    let key = format!(
+        "{} {} {}\n{}\nstrict_offset={}\nwiden_base85_groups={:?}\n{regex}",
+        env!("CARGO_PKG_VERSION"),
+        CONVERSION_REVISION,
+        FORMAT_VERSION,
+        transform_names(cli).join(","),
+        cli.strict_offset,
+        // the widened groups depend on the limit
+        cli.widen_base85_groups.then_some(cli.max_group_links),
+    );
+    if let Some(conversion) = cache
+        .get(&key)
+        .and_then(|bytes| Conversion::from_bytes(&bytes))
+    {
+        let limits = limits(cli);
+        for (limit, peak) in Limit::ALL.into_iter().zip(conversion.peaks) {
+            limits.check(limit, peak)?;
         }
-        let alphabet = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
-
-        let mut ones = 0_u8;
-        let mut zeroes = 0_u8;
-        for bit in 0..6 {
-            zeroes <<= 1;
-            ones <<= 1;
-            match list.get(bit) {
-                Some(true) => {
-                    ones |= 1;
-                    zeroes |= 1;
-                }
-                Some(false) => {}
-                None => {
-                    ones |= 1;
-                }
-            }
+        return Ok(conversion);
+    }
+
+    let conversion = convert(cli, regex, None)?;
+    if let Err(err) = cache.put(&key, &conversion.to_bytes()) {
+        eprintln!("warning: can't write to the cache: {err}");
+    }
+    Ok(conversion)
+}
+
+/// Encode the pattern, adding the automata built along the way to `trace`
+fn convert(
+    cli: &Cli,
+    regex: &str,
+    mut trace: Option<&mut GraphTrace>,
+) -> Result<Conversion, Error> {
+    let peaks = Arc::new(Peaks::default());
+    let limits = Limits {
+        peaks: Some(peaks.clone()),
+        ..limits(cli)
+    };
+    let nfa = regex_to_nfa_limited(regex, &limits)?;
+    let nfa_states = nfa.all_states().len();
+    if let Some(trace) = trace.as_deref_mut() {
+        trace.push("nfa", &nfa);
+    }
+
+    let mut dfa = determine_and_min_nfa_limited(nfa, &limits)?;
+    let dfa_states = dfa.all_states().len();
+    let mut step = |name: &str, dfa: &Dfa<u8>| {
+        if let Some(trace) = trace.as_deref_mut() {
+            trace.push(name, dfa);
+        }
+    };
+    step("dfa", &dfa);
+
+    if cli.ignore_case {
+        dfa = case_insensitive_dfa_limited(&dfa, &limits)?;
+        step("ignore-case", &dfa);
+    }
+
+    if let Some(utf16) = cli.utf16 {
+        let endianness = match utf16 {
+            Utf16::Le => Endianness::Little,
+            Utf16::Be => Endianness::Big,
+        };
+        dfa = utf16_dfa_limited(&dfa, endianness, &limits)?;
+        step("utf16", &dfa);
+    }
+
+    if let Some(XorKeys(keys)) = &cli.xor {
+        dfa = match keys.as_slice() {
+            [key] => determine_and_min_nfa_limited(xor_dfa(&dfa, *key).to_nfa(), &limits)?,
+            keys => xor_any_dfa_limited(&dfa, keys.iter().copied(), &limits)?,
+        };
+        step("xor", &dfa);
+    }
+
+    if let Some(HexKey(key)) = &cli.xor_key {
+        dfa = xor_repeating_dfa_limited(&dfa, key, &limits)?;
+        step("xor-key", &dfa);
+    }
+
+    if let Some(escape) = cli.escape {
+        let scheme = match escape {
+            Escape::C => EscapeScheme::C,
+            Escape::Json => EscapeScheme::Json,
+            Escape::Js => EscapeScheme::JavaScript,
+            Escape::Python => EscapeScheme::Python,
+        };
+        dfa = escape_dfa_limited(&dfa, scheme, &limits)?;
+        step("escape", &dfa);
+    }
+
+    if let Some(entities) = cli.entities {
+        let flavor = match entities {
+            Entities::Html => EntityFlavor::Html,
+            Entities::Xml => EntityFlavor::Xml,
+        };
+        dfa = entity_encode_dfa_limited(&dfa, flavor, &limits)?;
+        step("entities", &dfa);
+    }
+
+    if cli.url_encode {
+        let options = PercentOptions {
+            double_encoding: cli.url_double_encode,
+            plus_for_space: cli.url_plus_space,
+        };
+        dfa = percent_encode_dfa_limited(&dfa, &options, &limits)?;
+        step("url", &dfa);
+    }
+
+    if cli.quoted_printable {
+        dfa = quoted_printable_dfa_limited(&dfa, &limits)?;
+        step("quoted-printable", &dfa);
+    }
+
+    let pipeline = Pipeline {
+        stages: stages(cli),
+        strict_offset: cli.strict_offset,
+        limits: limits.clone(),
+    };
+    let dfa = pipeline.run_traced(&dfa, trace)?;
+    // the last transform may have built it without limits
+    limits.check_automata(&dfa)?;
+
+    Ok(Conversion {
+        dfa,
+        nfa_states,
+        dfa_states,
+        peaks: Limit::ALL.map(|limit| peaks.get(limit)),
+    })
+}
+
+fn limits(cli: &Cli) -> Limits {
+    Limits {
+        max_dfa_states: cli.max_dfa_states,
+        max_links: cli.max_links,
+        max_regex_len: cli.max_regex_len,
+        max_implode_paths: cli.max_implode_paths,
+        max_group_links: Some(cli.max_group_links),
+        peaks: None,
+    }
+}
+
+/// Final encodings, from `--pipeline` or `--encoding`
+fn stages(cli: &Cli) -> Vec<Stage> {
+    let over_group_links = if cli.widen_base85_groups {
+        OverGroupLinks::Widen
+    } else {
+        OverGroupLinks::Fail
+    };
+    let stages = match (&cli.pipeline, cli.encoding.unwrap_or(Encoding::Base64)) {
+        (Some(stages), _) => stages.clone(),
+        (None, Encoding::Base64) => vec![Stage::BitGroups(BitGroupEncoding::Base64)],
+        (None, Encoding::Base32) => vec![Stage::BitGroups(BitGroupEncoding::Base32)],
+        (None, Encoding::Hex) => vec![Stage::BitGroups(BitGroupEncoding::Hex)],
+        (None, Encoding::Ascii85) => vec![Stage::Base85(Base85Alphabet::Ascii85, over_group_links)],
+        (None, Encoding::Z85) => vec![Stage::Base85(Base85Alphabet::Z85, over_group_links)],
+        (None, Encoding::Raw) => vec![],
+    };
+    stages
+        .into_iter()
+        .map(|stage| match stage {
+            Stage::Base85(alphabet, _) => Stage::Base85(alphabet, over_group_links),
+            stage => stage,
+        })
+        .collect()
+}
+
+/// Transformations applied by [convert], in order
+fn transform_names(cli: &Cli) -> Vec<String> {
+    let mut names = Vec::new();
+    if cli.ignore_case {
+        names.push("ignore-case".to_string());
+    }
+    if let Some(utf16) = cli.utf16 {
+        names.push(format!("utf16{}", value_name(utf16)));
+    }
+    if let Some(XorKeys(keys)) = &cli.xor {
+        let keys = keys.iter().map(|key| format!("{key:#04x}")).join(",");
+        names.push(format!("xor={keys}"));
+    }
+    if let Some(HexKey(key)) = &cli.xor_key {
+        let key = key.iter().map(|byte| format!("{byte:02x}")).join("");
+        names.push(format!("xor-key={key}"));
+    }
+    if let Some(escape) = cli.escape {
+        names.push(value_name(escape));
+    }
+    if let Some(entities) = cli.entities {
+        names.push(value_name(entities));
+    }
+    if cli.url_encode {
+        names.push("url".to_string());
+        if cli.url_double_encode {
+            names.push("url-double-encode".to_string());
+        }
+        if cli.url_plus_space {
+            names.push("url-plus-space".to_string());
+        }
+    }
+    if cli.quoted_printable {
+        names.push("quoted-printable".to_string());
+    }
+    names.extend(stages(cli).iter().map(Stage::to_string));
+    names
+}
+
+fn value_name(value: impl ValueEnum) -> String {
+    value.to_possible_value().unwrap().get_name().to_string()
+}
+
+/// Convert each line of the file (`-` for stdin), `regex` or `id<TAB>regex`, on its own and
+/// write a JSON object per line. Failed lines are reported in their object, and make the
+/// process exit with an error once every line is converted
+///
+/// A line containing a tab is always read as `id<TAB>regex`, split at its first tab.
+fn convert_batch(cli: &Cli, path: &Path) {
+    let content = if path == Path::new("-") {
+        io::read_to_string(stdin())
+    } else {
+        fs::read_to_string(path)
+    }
+    .unwrap_or_else(|err| exit_with(ErrorKind::Io, format!("{}: {err}", path.display())));
+
+    let json = |s: &str| {
+        let mut out = String::new();
+        write_json_string(&mut out, s).unwrap();
+        out
+    };
+    let options = format!(
+        "{{\"transforms\":[{}],\"strict_offset\":{},\"format\":{},\"syntax\":{}}}",
+        transform_names(cli).iter().map(|name| json(name)).join(","),
+        cli.strict_offset,
+        json(&value_name(cli.format)),
+        json(&value_name(cli.syntax)),
+    );
+
+    let cache = open_cache(cli);
+    let mut failed = false;
+    for (idx, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
         }
+        let number = idx + 1;
+        let (id, regex) = match line.split_once('\t') {
+            Some((id, regex)) => (id.to_string(), regex),
+            None => (number.to_string(), line),
+        };
 
-        alphabet
-            .chars()
-            .enumerate()
-            .filter_map(|(idx, c)| {
-                let idx = idx as u8;
-                let mask = !(zeroes ^ ones);
-                if idx & mask == zeroes & mask {
-                    Some(c)
-                } else {
-                    None
-                }
+        let result = line_sid(cli, idx)
+            .ok_or_else(|| format!("sid {} + {idx} is over {}", cli.sid, u32::MAX))
+            .and_then(|sid| {
+                convert_cached(cli, cache.as_ref(), regex)
+                    .and_then(|conversion| {
+                        let output = render_bytes(cli, regex, sid, &conversion.dfa)?;
+                        Ok((conversion, output))
+                    })
+                    .map_err(|err| err.to_string())
+            });
+        let result = match result {
+            Ok((conversion, output)) => {
+                let sizes = format!(
+                    "{{\"nfa_states\":{},\"dfa_states\":{},\"encoded_states\":{},\"output_len\":{}}}",
+                    conversion.nfa_states,
+                    conversion.dfa_states,
+                    conversion.dfa.all_states().len(),
+                    output.len(),
+                );
+                format!(
+                    "\"output\":{},\"sizes\":{sizes},\"error\":null",
+                    json(&output)
+                )
+            }
+            Err(err) => {
+                failed = true;
+                format!("\"output\":null,\"sizes\":null,\"error\":{}", json(&err))
+            }
+        };
+        println!(
+            "{{\"line\":{number},\"id\":{},\"input\":{},\"options\":{options},{result}}}",
+            json(&id),
+            json(regex),
+        );
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// `sid` of the rule of the pattern at `idx` in the file, if it fits
+fn line_sid(cli: &Cli, idx: usize) -> Option<u32> {
+    u32::try_from(idx)
+        .ok()
+        .and_then(|idx| cli.sid.checked_add(idx))
+}
+
+fn convert_patterns(cli: &Cli, path: &Path) {
+    let content = fs::read_to_string(path)
+        .unwrap_or_else(|err| exit_with(ErrorKind::Io, format!("{}: {err}", path.display())));
+    let patterns = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect_vec();
+
+    let encoding = match cli.encoding.unwrap_or(Encoding::Base64) {
+        Encoding::Base64 => Some(BitGroupEncoding::Base64),
+        Encoding::Base32 => Some(BitGroupEncoding::Base32),
+        Encoding::Hex => Some(BitGroupEncoding::Hex),
+        Encoding::Raw => None,
+        Encoding::Ascii85 | Encoding::Z85 => exit_with(
+            ErrorKind::ArgumentConflict,
+            "--patterns only supports the base64, base32, hex and raw encodings",
+        ),
+    };
+
+    let limits = limits(cli);
+
+    let nfas = patterns
+        .iter()
+        .map(|pattern| {
+            regex_to_nfa_limited(pattern, &limits).unwrap_or_else(|err| {
+                exit_with(ErrorKind::InvalidValue, format!("`{pattern}`: {err}"))
             })
-            .collect_vec()
-    });
+        })
+        .collect_vec();
+    let mut dfa = Labelled::union(nfas)
+        .determine_and_min_limited(&limits)
+        .unwrap_or_else(|err| exit_with(ErrorKind::InvalidValue, err));
+
+    let sid = |id: PatternId| {
+        line_sid(cli, id).unwrap_or_else(|| {
+            exit_with(
+                ErrorKind::InvalidValue,
+                format!("sid {} + {id} is over {}", cli.sid, u32::MAX),
+            )
+        })
+    };
+
+    let Some(encoding) = encoding else {
+        for (id, pattern) in patterns.iter().enumerate() {
+            let dfa = pattern_dfa(&dfa, id, &limits)
+                .unwrap_or_else(|err| exit_with(ErrorKind::InvalidValue, err));
+            let output = render_bytes(cli, pattern, sid(id), &dfa)
+                .unwrap_or_else(|err| exit_with(ErrorKind::InvalidValue, err));
+            println!("{output}");
+        }
+        return;
+    };
+
+    if !cli.strict_offset && encoding.group_len() > 1 {
+        let all_bytes = (0..=255).collect_vec();
+        dfa = dfa
+            .with_any_prefix(&all_bytes, encoding.group_len() - 1)
+            .determine_and_min_limited(&limits)
+            .unwrap_or_else(|err| exit_with(ErrorKind::InvalidValue, err));
+    }
+    let encoded = bit_groups_labelled_limited(&dfa, encoding, &limits)
+        .unwrap_or_else(|err| exit_with(ErrorKind::InvalidValue, err));
+
+    for (id, pattern) in patterns.iter().enumerate() {
+        let dfa = pattern_dfa(&encoded, id, &limits)
+            .unwrap_or_else(|err| exit_with(ErrorKind::InvalidValue, err));
+        let output = render(cli, pattern, sid(id), &dfa)
+            .unwrap_or_else(|err| exit_with(ErrorKind::InvalidValue, err));
+        println!("{output}");
+    }
+}
+
+/// DFA of one of the patterns, the states shared with the other patterns may not be minimal for
+/// this one
+fn pattern_dfa<T: Eq + Hash + Clone + Debug>(
+    dfa: &Labelled<T>,
+    id: PatternId,
+    limits: &Limits,
+) -> Result<Dfa<T>, Error> {
+    determine_and_min_nfa_limited(dfa.pattern_dfa(id).to_nfa(), limits)
+}
+
+fn exit_with(kind: ErrorKind, message: impl Display) -> ! {
+    Cli::command().error(kind, message).exit()
+}
+
+#[derive(Clone)]
+struct XorKeys(Vec<u8>);
+
+fn parse_xor_keys(keys: &str) -> Result<XorKeys, String> {
+    if keys == "any" {
+        return Ok(XorKeys((0..=255).collect()));
+    }
+    keys.split(',')
+        .map(|key| {
+            let key = key.trim();
+            match key.strip_prefix("0x") {
+                Some(hex) => u8::from_str_radix(hex, 16),
+                None => key.parse(),
+            }
+            .map_err(|err| format!("invalid key `{key}`: {err}"))
+        })
+        .collect::<Result<_, _>>()
+        .map(XorKeys)
+}
+
+#[derive(Clone)]
+struct HexKey(Vec<u8>);
+
+fn parse_hex_key(key: &str) -> Result<HexKey, String> {
+    let key = key.strip_prefix("0x").unwrap_or(key);
+    if key.is_empty() || !key.len().is_multiple_of(2) || !key.is_ascii() {
+        return Err("expected an even number of hex digits".to_string());
+    }
+    (0..key.len())
+        .step_by(2)
+        .map(|idx| {
+            u8::from_str_radix(&key[idx..idx + 2], 16).map_err(|err| format!("invalid key: {err}"))
+        })
+        .collect::<Result<_, _>>()
+        .map(HexKey)
+}
 
-    //dbg!(&nfa3);
+/// Regex or rule written for the encoded pattern
+fn render<T: HirSymbol + ContentSymbol>(
+    cli: &Cli,
+    input: &str,
+    sid: u32,
+    dfa: &Dfa<T>,
+) -> Result<String, Error> {
+    let hir_options = HirOptions {
+        greedy: cli.greedy,
+        counted_repetitions: cli.counted_repetitions,
+    };
+    let hir = dfa_to_hir(dfa, &hir_options);
+    let regex = match (cli.format, cli.syntax) {
+        (Format::Regex, Syntax::Rust) => hir_to_rust(&hir),
+        _ => hir_to_portable(&hir),
+    };
+    limits(cli).check(Limit::RegexLen, regex.len())?;
 
-    let min_imploded = determine_and_min_nfa(imploded.to_nfa());
-    //automata_to_dot(&mut stdout(), &min_imploded).unwrap();
+    let flavor = match cli.format {
+        Format::Regex => return Ok(regex),
+        Format::Suricata => RuleFlavor::Suricata,
+        Format::Snort => RuleFlavor::Snort,
+    };
 
-    let regex = dfa_to_hir(&min_imploded);
-    println!("{regex}")
+    let rule = Rule {
+        header: cli.rule_header.clone(),
+        msg: cli
+            .msg
+            .clone()
+            .unwrap_or_else(|| format!("regex-conv: {input}")),
+        sid,
+        contents: content_literals(dfa, MIN_CONTENT_LEN),
+        pcre: regex,
+    };
+    let mut out = Vec::new();
+    write_rule(&mut out, &rule, flavor).unwrap();
+    Ok(String::from_utf8(out).unwrap().trim_end().to_string())
+}
+
+fn render_bytes(cli: &Cli, input: &str, sid: u32, dfa: &Dfa<u8>) -> Result<String, Error> {
+    match as_text(dfa) {
+        Some(text) => render(cli, input, sid, &text),
+        None => render(cli, input, sid, dfa),
+    }
+}
+
+/// Text encodings read better as a char regex
+fn as_text(dfa: &Dfa<u8>) -> Option<Dfa<char>> {
+    dfa.links
+        .iter()
+        .all(|link| link.symbol.is_ascii())
+        .then(|| explode_dfa(dfa, |byte: &u8| vec![*byte as char]))
+}
+
+#[cfg(test)]
+mod tests {
+    use regex_conv::{determine::determine_and_min_nfa, hir_to_nfa::regex_to_nfa};
+
+    use super::*;
+
+    #[test]
+    fn conversion_bytes() {
+        let dfa = determine_and_min_nfa(regex_to_nfa("a(b|c)*d").unwrap());
+        let conversion = Conversion {
+            dfa: dfa.clone(),
+            nfa_states: 300,
+            dfa_states: 4,
+            peaks: [5, 6, 0, 7, 8],
+        };
+        let bytes = conversion.to_bytes();
+
+        let back = Conversion::from_bytes(&bytes).unwrap();
+        assert!(back.dfa.equivalent(&dfa));
+        assert_eq!((back.nfa_states, back.dfa_states), (300, 4));
+        assert_eq!(back.peaks, [5, 6, 0, 7, 8]);
+
+        for len in 0..bytes.len() {
+            assert!(Conversion::from_bytes(&bytes[..len]).is_none(), "{len}");
+        }
+        assert!(Conversion::from_bytes(&[&bytes[..], &[0]].concat()).is_none());
+        assert!(Conversion::from_bytes(&[0xFF; 12]).is_none());
+    }
+
+    #[test]
+    fn cached_limits() {
+        let dir = std::env::temp_dir().join(format!("regex-conv-limits-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = Cache::new(&dir).unwrap();
+        let regex = "(a|b)*a(a|b){3}";
+        let cli =
+            |args: &[&str]| Cli::parse_from(["regex-conv"].iter().chain(args).chain([&regex]));
+
+        let unlimited = convert_cached(&cli(&[]), Some(&cache), regex).unwrap();
+        // the intermediate automata are larger than the encoded one
+        let links = unlimited.peaks[Limit::Links as usize];
+        assert!(links > unlimited.dfa.links.len());
+
+        for (limit, flag) in [
+            (Limit::DfaStates, "--max-dfa-states"),
+            (Limit::Links, "--max-links"),
+        ] {
+            let peak = unlimited.peaks[limit as usize];
+            for max in [peak - 1, peak] {
+                let cli = cli(&[flag, &max.to_string()]);
+                let fresh = convert(&cli, regex, None).map(|_| ());
+                let cached = convert_cached(&cli, Some(&cache), regex).map(|_| ());
+                assert_eq!(fresh.is_ok(), max == peak, "{flag} {max}");
+                assert_eq!(
+                    cached.map_err(|err| err.to_string()),
+                    fresh.map_err(|err| err.to_string()),
+                    "{flag} {max}"
+                );
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
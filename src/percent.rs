@@ -0,0 +1,144 @@
+use itertools::Itertools;
+
+use crate::{
+    automata::Dfa, determine::determine_and_min_nfa_limited, error::Error, limits::Limits,
+    transducer::Transducer,
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PercentOptions {
+    /// Also match `%25XX`, the percent-encoding of `%XX`
+    pub double_encoding: bool,
+    /// Also match `+` for a space, as in HTML forms
+    pub plus_for_space: bool,
+}
+
+/// Match every URL encoding of the pattern: each byte is written literally (when allowed in a
+/// URL), or as `%XX` with hex digits in any case, the forms being freely mixed
+pub fn percent_encode_dfa(dfa: &Dfa<u8>, options: &PercentOptions) -> Dfa<u8> {
+    percent_encode_dfa_limited(dfa, options, &Limits::default()).expect("no limits")
+}
+
+/// [percent_encode_dfa], failing when the automata built go over `limits`
+pub fn percent_encode_dfa_limited(
+    dfa: &Dfa<u8>,
+    options: &PercentOptions,
+    limits: &Limits,
+) -> Result<Dfa<u8>, Error> {
+    let transducer = Transducer::substitution(0..=255, |byte: &u8| renderings(*byte, options));
+    determine_and_min_nfa_limited(transducer.apply_limited(&dfa.to_nfa(), limits)?, limits)
+}
+
+fn renderings(byte: u8, options: &PercentOptions) -> Vec<Vec<u8>> {
+    let mut renderings = Vec::new();
+
+    if is_url_char(byte) {
+        renderings.push(vec![byte]);
+    }
+    if byte == b' ' && options.plus_for_space {
+        renderings.push(vec![b'+']);
+    }
+
+    for (high, low) in hex_digits(byte >> 4)
+        .into_iter()
+        .cartesian_product(hex_digits(byte & 0xF))
+    {
+        renderings.push(vec![b'%', high, low]);
+        if options.double_encoding {
+            renderings.push(vec![b'%', b'2', b'5', high, low]);
+        }
+    }
+
+    renderings
+}
+
+/// Unreserved and reserved characters of RFC 3986, `%` excluded
+fn is_url_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"-._~:/?#[]@!$&'()*+,;=".contains(&byte)
+}
+
+fn hex_digits(nibble: u8) -> Vec<u8> {
+    let digit = b"0123456789ABCDEF"[nibble as usize];
+    if digit.is_ascii_alphabetic() {
+        vec![digit, digit.to_ascii_lowercase()]
+    } else {
+        vec![digit]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{determine::determine_and_min_nfa, hir_to_nfa::regex_to_nfa};
+
+    fn accepts(regex: &str, options: &PercentOptions, text: &[u8]) -> bool {
+        let dfa = determine_and_min_nfa(regex_to_nfa(regex).unwrap());
+        let dfa = percent_encode_dfa(&dfa, options);
+        let mut states = dfa.initial_states.clone();
+        for byte in text {
+            states = dfa
+                .links
+                .iter()
+                .filter(|link| states.contains(&link.from) && link.symbol == *byte)
+                .map(|link| link.to)
+                .collect();
+        }
+        states.iter().any(|state| dfa.accept_states.contains(state))
+    }
+
+    #[test]
+    fn mixed_forms() {
+        let options = PercentOptions::default();
+        for text in [&b"a/b"[..], b"a%2fb", b"a%2Fb", b"%61%2F%62", b"%61/b"] {
+            assert!(accepts("a/b", &options, text), "{text:?}");
+        }
+        for text in [&b"a%2Gb"[..], b"a%%2Fb", b"a%2F", b"a%2Fbb"] {
+            assert!(!accepts("a/b", &options, text), "{text:?}");
+        }
+        // hex digits of any case, even mixed
+        for text in [&b"%FA"[..], b"%fa", b"%Fa", b"%fA"] {
+            assert!(accepts(r"\xFA", &options, text), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn forced_encoding() {
+        let options = PercentOptions::default();
+        // a space, `%`, a quote, a control byte and a non-ASCII byte are never literal
+        for (regex, literal, encoded) in [
+            ("a b", &b"a b"[..], &b"a%20b"[..]),
+            ("100%", b"100%", b"100%25"),
+            ("\"", b"\"", b"%22"),
+            (r"\x00", b"\x00", b"%00"),
+            (r"\xFF", b"\xFF", b"%ff"),
+        ] {
+            assert!(!accepts(regex, &options, literal), "{regex}");
+            assert!(accepts(regex, &options, encoded), "{regex}");
+        }
+    }
+
+    #[test]
+    fn plus_for_space() {
+        let plus = PercentOptions {
+            plus_for_space: true,
+            ..Default::default()
+        };
+        assert!(accepts("a b", &plus, b"a+b"));
+        assert!(accepts("a b", &plus, b"a%20b"));
+        assert!(!accepts("a b", &PercentOptions::default(), b"a+b"));
+        // a literal `+` is still `+` or `%2B`
+        assert!(accepts(r"a\+b", &plus, b"a%2Bb"));
+        assert!(!accepts(r"a\+b", &plus, b"a%20b"));
+    }
+
+    #[test]
+    fn double_encoding() {
+        let double = PercentOptions {
+            double_encoding: true,
+            ..Default::default()
+        };
+        assert!(accepts("a/b", &double, b"a%252fb"));
+        assert!(accepts("a/b", &double, b"a%2Fb"));
+        assert!(!accepts("a/b", &PercentOptions::default(), b"a%252fb"));
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use itertools::Itertools;
+
+use crate::{
+    automata::Dfa,
+    base85::{base85_any_offset_dfa_limited, base85_dfa_limited, Base85Alphabet, OverGroupLinks},
+    bit_groups::{bit_groups_dfa_traced, BitGroupEncoding},
+    determine::determine_and_min_nfa_limited,
+    entity::{entity_encode_dfa_limited, EntityFlavor},
+    error::Error,
+    escape::{escape_dfa_limited, EscapeScheme},
+    explode::explode_dfa,
+    limits::Limits,
+    percent::{percent_encode_dfa_limited, PercentOptions},
+    quoted_printable::quoted_printable_dfa_limited,
+    trace::GraphTrace,
+    utf16::{utf16_dfa_limited, Endianness},
+};
+
+/// One encoding of a [Pipeline]
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    BitGroups(BitGroupEncoding),
+    Base85(Base85Alphabet, OverGroupLinks),
+    Utf16(Endianness),
+    Percent(PercentOptions),
+    QuotedPrintable,
+    Escape(EscapeScheme),
+    Entities(EntityFlavor),
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(match name {
+            "base64" => Stage::BitGroups(BitGroupEncoding::Base64),
+            "base32" => Stage::BitGroups(BitGroupEncoding::Base32),
+            "hex" => Stage::BitGroups(BitGroupEncoding::Hex),
+            "ascii85" => Stage::Base85(Base85Alphabet::Ascii85, OverGroupLinks::Fail),
+            "z85" => Stage::Base85(Base85Alphabet::Z85, OverGroupLinks::Fail),
+            "utf16le" => Stage::Utf16(Endianness::Little),
+            "utf16be" => Stage::Utf16(Endianness::Big),
+            "url" => Stage::Percent(PercentOptions::default()),
+            "quoted-printable" => Stage::QuotedPrintable,
+            "c" => Stage::Escape(EscapeScheme::C),
+            "json" => Stage::Escape(EscapeScheme::Json),
+            "js" => Stage::Escape(EscapeScheme::JavaScript),
+            "python" => Stage::Escape(EscapeScheme::Python),
+            "html" => Stage::Entities(EntityFlavor::Html),
+            "xml" => Stage::Entities(EntityFlavor::Xml),
+            _ => return Err(format!("unknown stage `{name}`")),
+        })
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::BitGroups(BitGroupEncoding::Base64) => "base64",
+            Stage::BitGroups(BitGroupEncoding::Base32) => "base32",
+            Stage::BitGroups(BitGroupEncoding::Hex) => "hex",
+            Stage::Base85(Base85Alphabet::Ascii85, _) => "ascii85",
+            Stage::Base85(Base85Alphabet::Z85, _) => "z85",
+            Stage::Utf16(Endianness::Little) => "utf16le",
+            Stage::Utf16(Endianness::Big) => "utf16be",
+            Stage::Percent(_) => "url",
+            Stage::QuotedPrintable => "quoted-printable",
+            Stage::Escape(EscapeScheme::C) => "c",
+            Stage::Escape(EscapeScheme::Json) => "json",
+            Stage::Escape(EscapeScheme::JavaScript) => "js",
+            Stage::Escape(EscapeScheme::Python) => "python",
+            Stage::Entities(EntityFlavor::Html) => "html",
+            Stage::Entities(EntityFlavor::Xml) => "xml",
+        })
+    }
+}
+
+impl Stage {
+    /// Match the encodings of the strings matched by `dfa`
+    pub fn apply(&self, dfa: &Dfa<u8>, strict_offset: bool) -> Dfa<u8> {
+        self.apply_traced(dfa, strict_offset, &Limits::default(), None)
+            .expect("no limits")
+    }
+
+    /// [Stage::apply] within `limits`, adding the intermediate automata of the stage to `trace`
+    pub fn apply_traced(
+        &self,
+        dfa: &Dfa<u8>,
+        strict_offset: bool,
+        limits: &Limits,
+        trace: Option<&mut GraphTrace>,
+    ) -> Result<Dfa<u8>, Error> {
+        let all_bytes = (0..=255).collect_vec();
+        // the pattern can start at any offset in a group of bytes
+        let any_offset = |group_len: usize| {
+            if strict_offset || group_len == 1 {
+                Ok(dfa.clone())
+            } else {
+                determine_and_min_nfa_limited(
+                    dfa.with_any_prefix(&all_bytes, group_len - 1),
+                    limits,
+                )
+            }
+        };
+
+        let encoded =
+            match self {
+                Stage::BitGroups(encoding) => to_bytes(&bit_groups_dfa_traced(
+                    &any_offset(encoding.group_len())?,
+                    *encoding,
+                    limits,
+                    trace,
+                )?),
+                Stage::Base85(alphabet, over_group_links) if strict_offset => to_bytes(
+                    &base85_dfa_limited(dfa, *alphabet, *over_group_links, limits)?,
+                ),
+                Stage::Base85(alphabet, over_group_links) => to_bytes(
+                    &base85_any_offset_dfa_limited(dfa, *alphabet, *over_group_links, limits)?,
+                ),
+                Stage::Utf16(endianness) => utf16_dfa_limited(dfa, *endianness, limits)?,
+                Stage::Percent(options) => percent_encode_dfa_limited(dfa, options, limits)?,
+                Stage::QuotedPrintable => quoted_printable_dfa_limited(dfa, limits)?,
+                Stage::Escape(scheme) => escape_dfa_limited(dfa, *scheme, limits)?,
+                Stage::Entities(flavor) => entity_encode_dfa_limited(dfa, *flavor, limits)?,
+            };
+        // the chars of the encoded text are written as UTF-8 after the limited steps
+        limits.check_automata(&encoded)?;
+        Ok(encoded)
+    }
+}
+
+/// Encodings applied one after the other, each stage encoding the output of the previous one
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    pub stages: Vec<Stage>,
+    /// Only match content starting at the beginning of a group of bytes (see [Stage::apply])
+    pub strict_offset: bool,
+    pub limits: Limits,
+}
+
+impl Pipeline {
+    pub fn run(&self, dfa: &Dfa<u8>) -> Result<Dfa<u8>, Error> {
+        self.run_traced(dfa, None)
+    }
+
+    /// [Pipeline::run], adding the automata built by each stage to `trace`
+    pub fn run_traced(
+        &self,
+        dfa: &Dfa<u8>,
+        mut trace: Option<&mut GraphTrace>,
+    ) -> Result<Dfa<u8>, Error> {
+        self.stages.iter().try_fold(dfa.clone(), |dfa, stage| {
+            let mut stage_trace = trace
+                .as_ref()
+                .map(|trace| GraphTrace::new(trace.format, trace.dot_options));
+            let encoded =
+                stage.apply_traced(&dfa, self.strict_offset, &self.limits, stage_trace.as_mut())?;
+            if let Some(trace) = trace.as_deref_mut() {
+                for (name, dot) in stage_trace.into_iter().flat_map(|t| t.graphs) {
+                    trace.graphs.push((format!("{stage}-{name}"), dot));
+                }
+                trace.push(stage.to_string(), &encoded);
+            }
+            Ok(encoded)
+        })
+    }
+}
+
+/// UTF-8 bytes of the encoded text, to feed it to the next stage
+pub fn to_bytes(dfa: &Dfa<char>) -> Dfa<u8> {
+    explode_dfa(dfa, |c: &char| c.to_string().into_bytes())
+}
@@ -0,0 +1,120 @@
+use crate::{
+    automata::{Dfa, MaybeSymbol},
+    determine::determine_and_min_nfa_limited,
+    error::Error,
+    limits::Limits,
+    transducer::{Transducer, Transduction},
+};
+
+const SOFT_BREAKS: [&[u8]; 2] = [b"=\r\n", b"=\n"];
+
+/// Match every quoted-printable encoding of the pattern (RFC 2045): each byte is written
+/// literally when allowed or as `=XX`, and soft line breaks `=\r\n` (or `=\n`) can be inserted
+/// between any two bytes
+///
+/// Only CRLF is a line break of the text, lone CR and LF are encoded. A space or tab before a
+/// line break is encoded too, it would be removed as trailing whitespace.
+pub fn quoted_printable_dfa(dfa: &Dfa<u8>) -> Dfa<u8> {
+    quoted_printable_dfa_limited(dfa, &Limits::default()).expect("no limits")
+}
+
+/// [quoted_printable_dfa], failing when the automata built go over `limits`
+pub fn quoted_printable_dfa_limited(dfa: &Dfa<u8>, limits: &Limits) -> Result<Dfa<u8>, Error> {
+    // 0: nothing read, 1: a byte read, 2: a soft break written after a byte, 3: a space or tab
+    // written, 4: the CR of a line break written
+    let mut transducer = Transducer::new();
+    transducer.initial_states.insert(0);
+    transducer.accept_states.extend([0, 1, 3, 4]);
+    let mut link = |from, to, byte, output: &[u8]| {
+        let input = MaybeSymbol::Symbol(byte);
+        let output = output.to_vec();
+        transducer.link(from, to, Transduction { input, output });
+    };
+
+    for from in [0, 1, 2, 3] {
+        for byte in 0..=255 {
+            link(from, 1, byte, format!("={byte:02X}").as_bytes());
+            if is_literal(byte) {
+                link(from, 1, byte, &[byte]);
+            }
+        }
+        for byte in [b' ', b'\t'] {
+            link(from, 3, byte, &[byte]);
+        }
+        if from != 3 {
+            link(from, 4, b'\r', b"\r");
+        }
+    }
+    link(4, 1, b'\n', b"\n");
+    // the pattern may start between the CR and the LF of a line break
+    link(0, 1, b'\n', b"\n");
+
+    for from in [1, 2, 3] {
+        for soft_break in SOFT_BREAKS {
+            let input = MaybeSymbol::Epsilon;
+            let output = soft_break.to_vec();
+            transducer.link(from, 2, Transduction { input, output });
+        }
+    }
+
+    determine_and_min_nfa_limited(transducer.apply_limited(&dfa.to_nfa(), limits)?, limits)
+}
+
+/// Printable ASCII but `=`, the whitespaces and line breaks have their own states
+fn is_literal(byte: u8) -> bool {
+    byte.is_ascii_graphic() && byte != b'='
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{determine::determine_and_min_nfa, hir_to_nfa::regex_to_nfa};
+
+    fn accepts(regex: &str, text: &[u8]) -> bool {
+        let dfa = quoted_printable_dfa(&determine_and_min_nfa(regex_to_nfa(regex).unwrap()));
+        let mut states = dfa.initial_states.clone();
+        for byte in text {
+            states = dfa
+                .links
+                .iter()
+                .filter(|link| states.contains(&link.from) && link.symbol == *byte)
+                .map(|link| link.to)
+                .collect();
+        }
+        states.iter().any(|state| dfa.accept_states.contains(state))
+    }
+
+    #[test]
+    fn literal_or_encoded() {
+        assert!(accepts("a=b", b"a=3Db"));
+        assert!(!accepts("a=b", b"a=b"));
+        assert!(accepts("ab", b"=61b"));
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert!(accepts(r"a\r\nb", b"a\r\nb"));
+        assert!(accepts(r"a\r\nb", b"a=0D=0Ab"));
+        // lone CR and LF
+        assert!(!accepts(r"a\nb", b"a\nb"));
+        assert!(accepts(r"a\nb", b"a=0Ab"));
+        assert!(!accepts(r"a\rb", b"a\rb"));
+        assert!(accepts(r"a\r", b"a\r"));
+    }
+
+    #[test]
+    fn whitespace_before_line_break() {
+        assert!(!accepts(r"a \r\nb", b"a \r\nb"));
+        assert!(accepts(r"a \r\nb", b"a=20\r\nb"));
+        assert!(accepts(r"a\tb", b"a\tb"));
+        assert!(accepts(r"a\tb", b"a\t=\r\nb"));
+    }
+
+    #[test]
+    fn soft_breaks() {
+        assert!(accepts("ab", b"a=\r\nb"));
+        assert!(accepts("ab", b"a=\nb"));
+        assert!(accepts("ab", b"a=\n=\r\nb"));
+        assert!(!accepts("ab", b"=\nab"));
+    }
+}
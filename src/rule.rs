@@ -0,0 +1,189 @@
+use std::{hash::Hash, io};
+
+use crate::{automata::Dfa, literals::required_literals};
+
+/// Syntax of the generated IDS rule
+#[derive(Debug, Clone, Copy)]
+pub enum RuleFlavor {
+    /// Suricata (and Snort 2) syntax: `content:"..."; fast_pattern;`
+    Suricata,
+    /// Snort 3 syntax: `content:"...", fast_pattern;`
+    Snort,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// Everything before the options, e.g. `alert tcp any any -> any any`
+    pub header: String,
+    pub msg: String,
+    pub sid: u32,
+    /// Literals that every match contains, the first one is used as the fast pattern
+    pub contents: Vec<Vec<u8>>,
+    /// Regex written inside `pcre:"/.../"`
+    pub pcre: String,
+}
+
+pub fn write_rule(out: &mut impl io::Write, rule: &Rule, flavor: RuleFlavor) -> io::Result<()> {
+    let Rule {
+        header,
+        msg,
+        sid,
+        contents,
+        pcre,
+    } = rule;
+
+    let mut options = vec![format!("msg:\"{}\"", escape_msg(msg))];
+
+    for (idx, content) in contents.iter().enumerate() {
+        let content = escape_content(content);
+        options.push(match (idx, flavor) {
+            (0, RuleFlavor::Suricata) => format!("content:\"{content}\"; fast_pattern"),
+            (0, RuleFlavor::Snort) => format!("content:\"{content}\", fast_pattern"),
+            _ => format!("content:\"{content}\""),
+        });
+    }
+
+    options.push(format!("pcre:\"/{}/\"", escape_pcre(pcre)));
+    options.push(format!("sid:{sid}"));
+    options.push("rev:1".to_string());
+
+    match flavor {
+        RuleFlavor::Suricata => writeln!(out, "{header} ({};)", options.join("; ")),
+        RuleFlavor::Snort => writeln!(out, "{header} ( {}; )", options.join("; ")),
+    }
+}
+
+/// Symbols that can be written in a `content` keyword
+pub trait ContentSymbol: Clone + Eq + Hash {
+    fn push_bytes(&self, bytes: &mut Vec<u8>);
+}
+
+impl ContentSymbol for u8 {
+    fn push_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.push(*self);
+    }
+}
+
+impl ContentSymbol for char {
+    fn push_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(self.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+}
+
+/// Literals that every string accepted by the DFA contains, to be used as `content` keywords
+pub fn content_literals<T: ContentSymbol>(dfa: &Dfa<T>, min_len: usize) -> Vec<Vec<u8>> {
+    required_literals(dfa, min_len)
+        .into_iter()
+        .map(|literal| {
+            let mut bytes = Vec::new();
+            for symbol in literal {
+                symbol.push_bytes(&mut bytes);
+            }
+            bytes
+        })
+        .collect()
+}
+
+fn escape_msg(msg: &str) -> String {
+    let mut escaped = String::new();
+    for c in msg.chars() {
+        if matches!(c, '"' | ';' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Regex escapes are kept as is, only the rule delimiters are escaped
+fn escape_pcre(pcre: &str) -> String {
+    let mut escaped = String::new();
+    let mut in_escape = false;
+    for c in pcre.chars() {
+        if matches!(c, '"' | ';') || (c == '/' && !in_escape) {
+            escaped.push('\\');
+        }
+        in_escape = c == '\\' && !in_escape;
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_content(content: &[u8]) -> String {
+    content
+        .iter()
+        .map(|byte| match byte {
+            b'"' | b';' | b'\\' | b'|' => format!("|{byte:02X}|"),
+            byte if byte.is_ascii_graphic() || *byte == b' ' => (*byte as char).to_string(),
+            byte => format!("|{byte:02X}|"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{determine::determine_and_min_nfa, explode::explode_dfa, hir_to_nfa::regex_to_nfa};
+
+    fn rule_text(rule: &Rule, flavor: RuleFlavor) -> String {
+        let mut out = Vec::new();
+        write_rule(&mut out, rule, flavor).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn rule() -> Rule {
+        Rule {
+            header: "alert tcp any any -> any any".to_string(),
+            msg: r#"say "hi"; a\b"#.to_string(),
+            sid: 7,
+            contents: vec![b"ab\"c".to_vec(), b"\x00x|".to_vec()],
+            pcre: r#"a/b\/c;""#.to_string(),
+        }
+    }
+
+    #[test]
+    fn suricata() {
+        assert_eq!(
+            rule_text(&rule(), RuleFlavor::Suricata),
+            r#"alert tcp any any -> any any (msg:"say \"hi\"\; a\\b"; content:"ab|22|c"; fast_pattern; content:"|00|x|7C|"; pcre:"/a\/b\/c\;\"/"; sid:7; rev:1;)"#
+                .to_string()
+                + "\n"
+        );
+    }
+
+    #[test]
+    fn snort() {
+        assert_eq!(
+            rule_text(&rule(), RuleFlavor::Snort),
+            r#"alert tcp any any -> any any ( msg:"say \"hi\"\; a\\b"; content:"ab|22|c", fast_pattern; content:"|00|x|7C|"; pcre:"/a\/b\/c\;\"/"; sid:7; rev:1; )"#
+                .to_string()
+                + "\n"
+        );
+    }
+
+    #[test]
+    fn without_contents() {
+        let rule = Rule {
+            contents: Vec::new(),
+            pcre: "a|b".to_string(),
+            ..rule()
+        };
+        assert!(rule_text(&rule, RuleFlavor::Suricata)
+            .ends_with("\\b\"; pcre:\"/a|b/\"; sid:7; rev:1;)\n"));
+    }
+
+    #[test]
+    fn content_bytes() {
+        let dfa = determine_and_min_nfa(regex_to_nfa("xx(ab;c|ab;d)").unwrap());
+        assert_eq!(content_literals(&dfa, 2), vec![b"xxab;".to_vec()]);
+        // chars are written as UTF-8
+        let chars = explode_dfa(&dfa, |byte: &u8| match byte {
+            b'x' => vec!['é'],
+            byte => vec![*byte as char],
+        });
+        assert_eq!(
+            content_literals(&chars, 2),
+            vec!["ééab;".as_bytes().to_vec()]
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use std::io;
+
+use itertools::Itertools;
+
+use crate::{
+    automata::{Automata, State},
+    error::Error,
+};
+
+const MAGIC: &[u8; 4] = b"RCAU";
+const TRUNCATED: Error = Error::Binary("truncated or corrupted automaton");
+
+/// Version of the layout written by [automata_to_binary], files of other versions are rejected
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Symbols that can be written in the binary format
+pub trait BinarySymbol: Sized {
+    /// Tag of the symbol type, so that automata are read back with the type they were written
+    /// with
+    const KIND: u8;
+
+    fn write(&self, out: &mut Vec<u8>);
+    /// Read a symbol at the start of `input` and advance it
+    fn read(input: &mut &[u8]) -> Option<Self>;
+}
+
+impl BinarySymbol for u8 {
+    const KIND: u8 = 1;
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+
+    fn read(input: &mut &[u8]) -> Option<Self> {
+        let (byte, rest) = input.split_first()?;
+        *input = rest;
+        Some(*byte)
+    }
+}
+
+impl BinarySymbol for bool {
+    const KIND: u8 = 2;
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn read(input: &mut &[u8]) -> Option<Self> {
+        match u8::read(input)? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl BinarySymbol for char {
+    const KIND: u8 = 3;
+
+    fn write(&self, out: &mut Vec<u8>) {
+        write_varint(out, *self as u64);
+    }
+
+    fn read(input: &mut &[u8]) -> Option<Self> {
+        char::from_u32(read_varint(input)?.try_into().ok()?)
+    }
+}
+
+/// Write `n` in LEB128: 7 bits per byte, the high bit set on every byte but the last
+pub fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// Read an integer written by [write_varint] at the start of `input` and advance it
+///
+/// `None` when the input ends first or the integer doesn't fit in 64 bits.
+pub fn read_varint(input: &mut &[u8]) -> Option<u64> {
+    let mut n = 0_u64;
+    for shift in (0..64).step_by(7) {
+        let byte = u8::read(input)?;
+        let bits = (byte & 0x7F) as u64;
+        if bits.leading_zeros() < shift {
+            return None;
+        }
+        n |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Some(n);
+        }
+    }
+    None
+}
+
+/// Write the automata in a compact binary layout: magic, [FORMAT_VERSION], symbol
+/// [kind](BinarySymbol::KIND), then the initial states, the accept states and the links
+/// (from, to, symbol) as lists prefixed by their length, integers being LEB128 encoded
+pub fn automata_to_binary<T: BinarySymbol>(
+    out: &mut impl io::Write,
+    automata: &Automata<T>,
+) -> io::Result<()> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(FORMAT_VERSION);
+    bytes.push(T::KIND);
+
+    for states in [&automata.initial_states, &automata.accept_states] {
+        write_varint(&mut bytes, states.len() as u64);
+        for state in states.iter().sorted() {
+            write_varint(&mut bytes, *state as u64);
+        }
+    }
+
+    write_varint(&mut bytes, automata.links.len() as u64);
+    for link in &automata.links {
+        write_varint(&mut bytes, link.from as u64);
+        write_varint(&mut bytes, link.to as u64);
+        link.symbol.write(&mut bytes);
+    }
+
+    out.write_all(&bytes)
+}
+
+/// Read back an automaton written by [automata_to_binary] with the same symbol type
+pub fn automata_from_binary<T: BinarySymbol>(bytes: &[u8]) -> Result<Automata<T>, Error> {
+    let mut input = bytes
+        .strip_prefix(MAGIC)
+        .ok_or(Error::Binary("not an automaton file"))?;
+
+    match (u8::read(&mut input), u8::read(&mut input)) {
+        (Some(FORMAT_VERSION), Some(kind)) if kind == T::KIND => {}
+        (Some(FORMAT_VERSION), Some(_)) => return Err(Error::Binary("other symbol type")),
+        _ => return Err(Error::Binary("unsupported format version")),
+    }
+
+    let mut automata = Automata::new();
+
+    for states in [&mut automata.initial_states, &mut automata.accept_states] {
+        let len = read_varint(&mut input).ok_or(TRUNCATED)?;
+        for _ in 0..len {
+            states.insert(read_state(&mut input).ok_or(TRUNCATED)?);
+        }
+    }
+
+    let len = read_varint(&mut input).ok_or(TRUNCATED)?;
+    for _ in 0..len {
+        let from = read_state(&mut input).ok_or(TRUNCATED)?;
+        let to = read_state(&mut input).ok_or(TRUNCATED)?;
+        let symbol = T::read(&mut input).ok_or(TRUNCATED)?;
+        automata.link(from, to, symbol);
+    }
+
+    if !input.is_empty() {
+        return Err(Error::Binary("trailing bytes"));
+    }
+    Ok(automata)
+}
+
+fn read_state(input: &mut &[u8]) -> Option<State> {
+    read_varint(input)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        automata::Dfa, bit_groups::hex_dfa, determine::determine_and_min_nfa, explode::explode_dfa,
+        hir_to_nfa::regex_to_nfa,
+    };
+
+    fn dfa(regex: &str) -> Dfa<u8> {
+        determine_and_min_nfa(regex_to_nfa(regex).unwrap())
+    }
+
+    fn binary<T: BinarySymbol>(automata: &Automata<T>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        automata_to_binary(&mut bytes, automata).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        for n in [
+            0,
+            1,
+            0x7F,
+            0x80,
+            0x3FFF,
+            0x4000,
+            u32::MAX as u64,
+            1 << 63,
+            u64::MAX,
+        ] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, n);
+            assert_eq!(
+                bytes.len(),
+                (64 - n.leading_zeros() as usize).div_ceil(7).max(1)
+            );
+            let mut input = &bytes[..];
+            assert_eq!(read_varint(&mut input), Some(n));
+            assert!(input.is_empty());
+
+            for len in 0..bytes.len() {
+                assert_eq!(read_varint(&mut &bytes[..len]), None);
+            }
+        }
+        assert_eq!(read_varint(&mut &[0x80, 0x01, 0xFF][..]), Some(0x80));
+    }
+
+    #[test]
+    fn varint_overflow() {
+        let mut max = [0xFF; 10];
+        max[9] = 0x01;
+        assert_eq!(read_varint(&mut &max[..]), Some(u64::MAX));
+        for last in [0x02, 0x7F] {
+            max[9] = last;
+            assert_eq!(read_varint(&mut &max[..]), None);
+        }
+        assert_eq!(read_varint(&mut &[0x80; 11][..]), None);
+    }
+
+    #[test]
+    fn round_trips() {
+        let bytes = dfa("a(b|c)*[\\x00-\\xFF]{2}");
+        let back: Dfa<u8> = automata_from_binary(&binary(&bytes)).unwrap();
+        assert!(back.equivalent(&bytes));
+        assert_eq!(binary(&back), binary(&bytes));
+
+        let mut chars = hex_dfa(&bytes);
+        chars.link(0, 0, '😀');
+        let back: Dfa<char> = automata_from_binary(&binary(&chars)).unwrap();
+        assert!(back.equivalent(&chars));
+
+        let bits = explode_dfa(&bytes, |byte| {
+            (0..8).rev().map(|bit| byte >> bit & 1 == 1).collect()
+        });
+        let back: Dfa<bool> = automata_from_binary(&binary(&bits)).unwrap();
+        assert!(back.equivalent(&bits));
+
+        let back: Dfa<u8> = automata_from_binary(&binary(&Dfa::<u8>::new())).unwrap();
+        assert!(back.links.is_empty() && back.initial_states.is_empty());
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = binary(&dfa("ab*c|d"));
+        for len in 0..bytes.len() {
+            assert!(automata_from_binary::<u8>(&bytes[..len]).is_err(), "{len}");
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(automata_from_binary::<u8>(&trailing).is_err());
+    }
+
+    #[test]
+    fn corrupted() {
+        let header = |kind: u8| [&MAGIC[..], &[FORMAT_VERSION, kind]].concat();
+        let file = |kind: u8, body: &[u8]| [&header(kind)[..], body].concat();
+        let read_u8 = |bytes: &[u8]| automata_from_binary::<u8>(bytes).map(|_| ());
+
+        assert!(read_u8(&file(1, &[0, 0, 0])).is_ok());
+        assert!(read_u8(&file(1, &[1, 0, 1, 1, 1, 0, 1, 0x61])).is_ok());
+        for bytes in [
+            // magic, version and kind
+            [b"RCAV", &header(1)[4..], &[0, 0, 0]].concat(),
+            [&MAGIC[..], &[FORMAT_VERSION + 1, 1, 0, 0, 0]].concat(),
+            file(2, &[0, 0, 0]),
+            file(3, &[0, 0, 0]),
+            // lengths longer than the data, even huge ones
+            file(1, &[1, 0, 0]),
+            file(1, &[0, 0, 2, 0, 1, 0x61]),
+            file(
+                1,
+                &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+            ),
+            // states over u32
+            file(1, &[1, 0x80, 0x80, 0x80, 0x80, 0x10, 0, 0]),
+            file(1, &[0, 0, 1, 0, 0x80, 0x80, 0x80, 0x80, 0x10, 0x61]),
+        ] {
+            assert!(read_u8(&bytes).is_err(), "{bytes:?}");
+        }
+
+        // symbols out of their type
+        assert!(automata_from_binary::<bool>(&file(2, &[0, 0, 1, 0, 1, 2])).is_err());
+        let surrogate = [0, 0, 1, 0, 1, 0x80, 0xB0, 0x03];
+        assert!(automata_from_binary::<char>(&file(3, &surrogate)).is_err());
+        let over_unicode = [0, 0, 1, 0, 1, 0x80, 0x80, 0x44];
+        assert!(automata_from_binary::<char>(&file(3, &over_unicode)).is_err());
+    }
+
+    #[test]
+    fn any_corruption_without_panic() {
+        let bytes = binary(&dfa("(ab|c)+d?"));
+        for idx in 0..bytes.len() {
+            for mask in [0x01, 0x40, 0x80, 0xFF] {
+                let mut corrupted = bytes.clone();
+                corrupted[idx] ^= mask;
+                let _ = automata_from_binary::<u8>(&corrupted);
+            }
+        }
+    }
+}
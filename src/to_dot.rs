@@ -1,25 +1,233 @@
-use crate::automata::{Automata, Link};
-use std::{fmt::Display, io};
+use std::io;
 
-pub fn automata_to_dot<T: Display>(
+use itertools::Itertools;
+use regex_syntax::hir::Hir;
+
+use crate::{
+    automata::{Automata, MaybeSymbol},
+    transducer::Transduction,
+};
+
+/// Direction of the graph layout
+#[derive(Debug, Clone, Copy, Default)]
+pub enum RankDir {
+    #[default]
+    LeftRight,
+    TopBottom,
+    RightLeft,
+    BottomTop,
+}
+
+impl RankDir {
+    fn attribute(&self) -> &'static str {
+        match self {
+            RankDir::LeftRight => "LR",
+            RankDir::TopBottom => "TB",
+            RankDir::RightLeft => "RL",
+            RankDir::BottomTop => "BT",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DotOptions {
+    pub rankdir: RankDir,
+}
+
+/// Symbols written on the edges of a DOT or Mermaid graph
+pub trait DotSymbol {
+    fn dot_label(&self) -> String;
+
+    /// Position of the symbol in its alphabet, consecutive symbols of a link are written as a
+    /// range
+    fn ordinal(&self) -> Option<u32> {
+        None
+    }
+}
+
+impl DotSymbol for u8 {
+    fn dot_label(&self) -> String {
+        if self.is_ascii_graphic() {
+            (*self as char).to_string()
+        } else {
+            format!("\\x{self:02X}")
+        }
+    }
+
+    fn ordinal(&self) -> Option<u32> {
+        Some(*self as u32)
+    }
+}
+
+impl DotSymbol for char {
+    fn dot_label(&self) -> String {
+        if self.is_control() || self.is_whitespace() {
+            self.escape_unicode().to_string()
+        } else {
+            self.to_string()
+        }
+    }
+
+    fn ordinal(&self) -> Option<u32> {
+        Some(*self as u32)
+    }
+}
+
+impl DotSymbol for bool {
+    fn dot_label(&self) -> String {
+        (*self as u8).to_string()
+    }
+}
+
+impl DotSymbol for Hir {
+    fn dot_label(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl<T: DotSymbol> DotSymbol for MaybeSymbol<T> {
+    fn dot_label(&self) -> String {
+        match self {
+            MaybeSymbol::Symbol(symbol) => symbol.dot_label(),
+            MaybeSymbol::Epsilon => "ε".to_string(),
+        }
+    }
+
+    fn ordinal(&self) -> Option<u32> {
+        match self {
+            MaybeSymbol::Symbol(symbol) => symbol.ordinal(),
+            MaybeSymbol::Epsilon => None,
+        }
+    }
+}
+
+impl<I: DotSymbol, O: DotSymbol> DotSymbol for Transduction<I, O> {
+    fn dot_label(&self) -> String {
+        let output = self.output.iter().map(DotSymbol::dot_label).join("");
+        format!("{}/{output}", self.input.dot_label())
+    }
+}
+
+/// Write the automata in the Graphviz DOT language
+///
+/// Accept states are double circles, initial states are pointed by an arrow, and the links
+/// between two states are merged in a single edge, labelled like a regex class (`[A-Z]`).
+pub fn automata_to_dot<T: DotSymbol>(
     out: &mut impl io::Write,
     automata: &Automata<T>,
+    options: &DotOptions,
 ) -> io::Result<()> {
     writeln!(out, "digraph {{")?;
+    writeln!(out, "rankdir={}", options.rankdir.attribute())?;
+    writeln!(out, "node [shape=circle]")?;
 
-    for init in &automata.initial_states {
-        writeln!(out, "s{init} [label=\"init_{init}\"]")?;
+    for state in automata.all_states().into_iter().sorted() {
+        let shape = if automata.accept_states.contains(&state) {
+            "doublecircle"
+        } else {
+            "circle"
+        };
+        writeln!(out, "s{state} [label=\"{state}\", shape={shape}]")?;
     }
 
-    for accept in &automata.accept_states {
-        writeln!(out, "s{accept} [label=\"accept_{accept}\"]")?;
+    for init in automata.initial_states.iter().sorted() {
+        writeln!(out, "start{init} [shape=point, style=invis]")?;
+        writeln!(out, "start{init} -> s{init}")?;
     }
 
-    for link in &automata.links {
-        let Link { from, symbol, to } = link;
-        writeln!(out, "s{from} -> s{to} [label=\"{symbol}\"]")?;
+    let edges = automata
+        .links
+        .iter()
+        .map(|link| ((link.from, link.to), &link.symbol))
+        .into_group_map();
+    for ((from, to), symbols) in edges.into_iter().sorted_by_key(|(states, _)| *states) {
+        let label = escape(&edge_label(&symbols));
+        writeln!(out, "s{from} -> s{to} [label=\"{label}\"]")?;
     }
 
     writeln!(out, "}}")?;
     Ok(())
 }
+
+/// Label of an edge reading any of `symbols`
+pub(crate) fn edge_label<T: DotSymbol>(symbols: &[&T]) -> String {
+    if let [symbol] = symbols {
+        return symbol.dot_label();
+    }
+
+    let mut ordered = Vec::new();
+    let mut others = Vec::new();
+    for symbol in symbols {
+        match symbol.ordinal() {
+            Some(ordinal) => ordered.push((ordinal, class_label(symbol.dot_label()))),
+            None => others.push(symbol.dot_label()),
+        }
+    }
+
+    let ranges = ordered
+        .into_iter()
+        .sorted_by_key(|(ordinal, _)| *ordinal)
+        .dedup_by(|(a, _), (b, _)| a == b)
+        .map(|(ordinal, label)| (ordinal, label.clone(), ordinal, label))
+        .coalesce(
+            |(start, first, end, last), (next, next_first, next_end, next_last)| {
+                if end + 1 == next {
+                    Ok((start, first, next_end, next_last))
+                } else {
+                    Err((
+                        (start, first, end, last),
+                        (next, next_first, next_end, next_last),
+                    ))
+                }
+            },
+        )
+        .map(|(start, first, end, last)| match end - start {
+            0 => first,
+            1 => format!("{first}{last}"),
+            _ => format!("{first}-{last}"),
+        })
+        .join("");
+
+    others.sort();
+    if !ranges.is_empty() {
+        others.insert(0, format!("[{ranges}]"));
+    }
+    others.join(", ")
+}
+
+/// Escape the characters with a meaning in a regex class (`[\]-]`)
+fn class_label(label: String) -> String {
+    match label.as_str() {
+        "]" | "-" | "\\" | "^" => format!("\\{label}"),
+        _ => label,
+    }
+}
+
+/// Escape a DOT quoted string
+fn escape(label: &str) -> String {
+    let mut escaped = String::new();
+    for c in label.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn class_edge_labels() {
+        assert_eq!(edge_label(&[&b'-', &b']', &b'a']), r"[\-\]a]");
+        assert_eq!(edge_label(&[&b'\\', &b']', &b'^']), r"[\\-\^]");
+        assert_eq!(edge_label(&[&b'\\', &b'^']), r"[\\\^]");
+        assert_eq!(edge_label(&[&b'a', &b'b', &b'c', &0]), r"[\x00a-c]");
+        // a single symbol is not a class
+        assert_eq!(edge_label(&[&b']']), "]");
+    }
+}
@@ -0,0 +1,282 @@
+use std::io;
+
+use itertools::Itertools;
+
+use crate::{
+    automata::{Automata, MaybeSymbol, State},
+    error::Error,
+    json::{parse_json, JsonValue},
+};
+
+/// Symbols that can be written in JSON and read back
+pub trait JsonSymbol: Sized {
+    fn to_json(&self) -> JsonValue;
+    fn from_json(value: &JsonValue) -> Option<Self>;
+}
+
+/// A number
+impl JsonSymbol for u8 {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::Number(*self as f64)
+    }
+
+    fn from_json(value: &JsonValue) -> Option<Self> {
+        value.as_u64()?.try_into().ok()
+    }
+}
+
+/// A string of one character
+impl JsonSymbol for char {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::String(self.to_string())
+    }
+
+    fn from_json(value: &JsonValue) -> Option<Self> {
+        let mut chars = value.as_str()?.chars();
+        let c = chars.next()?;
+        chars.next().is_none().then_some(c)
+    }
+}
+
+impl JsonSymbol for bool {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::Bool(*self)
+    }
+
+    fn from_json(value: &JsonValue) -> Option<Self> {
+        match value {
+            JsonValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+/// `null` for epsilon
+impl<T: JsonSymbol> JsonSymbol for MaybeSymbol<T> {
+    fn to_json(&self) -> JsonValue {
+        match self {
+            MaybeSymbol::Symbol(symbol) => symbol.to_json(),
+            MaybeSymbol::Epsilon => JsonValue::Null,
+        }
+    }
+
+    fn from_json(value: &JsonValue) -> Option<Self> {
+        match value {
+            JsonValue::Null => Some(MaybeSymbol::Epsilon),
+            value => T::from_json(value).map(MaybeSymbol::Symbol),
+        }
+    }
+}
+
+/// `{"states": [...], "initial": [...], "accept": [...], "links": [{"from", "to", "symbol"}]}`
+///
+/// States are sorted to give the same document for the same automaton.
+pub fn automata_to_json_value<T: JsonSymbol>(automata: &Automata<T>) -> JsonValue {
+    let links = automata
+        .links
+        .iter()
+        .map(|link| {
+            JsonValue::Object(vec![
+                ("from".to_string(), JsonValue::Number(link.from as f64)),
+                ("to".to_string(), JsonValue::Number(link.to as f64)),
+                ("symbol".to_string(), link.symbol.to_json()),
+            ])
+        })
+        .collect();
+
+    JsonValue::Object(vec![
+        ("states".to_string(), states_json(automata.all_states())),
+        (
+            "initial".to_string(),
+            states_json(automata.initial_states.iter().copied()),
+        ),
+        (
+            "accept".to_string(),
+            states_json(automata.accept_states.iter().copied()),
+        ),
+        ("links".to_string(), JsonValue::Array(links)),
+    ])
+}
+
+/// Write the automata as a JSON document, see [automata_to_json_value]
+pub fn automata_to_json<T: JsonSymbol>(
+    out: &mut impl io::Write,
+    automata: &Automata<T>,
+) -> io::Result<()> {
+    writeln!(out, "{}", automata_to_json_value(automata))
+}
+
+/// Read back an automaton written by [automata_to_json]
+///
+/// `states` is optional, the states without links are only useful when they are initial or
+/// accept states.
+pub fn automata_from_json<T: JsonSymbol>(text: &str) -> Result<Automata<T>, Error> {
+    let json = parse_json(text)?;
+    let invalid = |message: &str| Error::Json(message.to_string());
+
+    let states = |key: &str| -> Result<Vec<State>, Error> {
+        let items = json
+            .get(key)
+            .and_then(JsonValue::as_array)
+            .ok_or_else(|| invalid(&format!("`{key}` should be an array of states")))?;
+        items.iter().map(|item| state(item, key)).collect()
+    };
+
+    let mut automata = Automata::new();
+    automata.initial_states = states("initial")?.into_iter().collect();
+    automata.accept_states = states("accept")?.into_iter().collect();
+
+    let links = json
+        .get("links")
+        .and_then(JsonValue::as_array)
+        .ok_or_else(|| invalid("`links` should be an array"))?;
+    for link in links {
+        let from = state(link.get("from").unwrap_or(&JsonValue::Null), "from")?;
+        let to = state(link.get("to").unwrap_or(&JsonValue::Null), "to")?;
+        let symbol = link
+            .get("symbol")
+            .and_then(T::from_json)
+            .ok_or_else(|| invalid("invalid link `symbol`"))?;
+        automata.link(from, to, symbol);
+    }
+
+    Ok(automata)
+}
+
+fn states_json(states: impl IntoIterator<Item = State>) -> JsonValue {
+    let states = states
+        .into_iter()
+        .sorted()
+        .map(|state| JsonValue::Number(state as f64));
+    JsonValue::Array(states.collect())
+}
+
+fn state(value: &JsonValue, key: &str) -> Result<State, Error> {
+    value
+        .as_u64()
+        .and_then(|state| state.try_into().ok())
+        .ok_or_else(|| Error::Json(format!("invalid state in `{key}`")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        automata::{Dfa, Nfa},
+        bit_groups::hex_dfa,
+        determine::determine_and_min_nfa,
+        explode::explode_dfa,
+        hir_to_nfa::regex_to_nfa,
+    };
+
+    fn json<T: JsonSymbol>(automata: &Automata<T>) -> String {
+        let mut out = Vec::new();
+        automata_to_json(&mut out, automata).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn round_trip<T: JsonSymbol>(automata: &Automata<T>) -> Automata<T> {
+        let text = json(automata);
+        let back = automata_from_json(&text).unwrap();
+        assert_eq!(json(&back), text);
+        back
+    }
+
+    #[test]
+    fn round_trips() {
+        let nfa = regex_to_nfa("a(b|c)*d?").unwrap();
+        let nfa_back: Nfa<u8> = round_trip(&nfa);
+        let dfa = determine_and_min_nfa(nfa);
+        assert!(determine_and_min_nfa(nfa_back).equivalent(&dfa));
+
+        let dfa_back: Dfa<u8> = round_trip(&dfa);
+        assert!(dfa_back.equivalent(&dfa));
+
+        let chars = hex_dfa(&dfa);
+        let chars_back: Dfa<char> = round_trip(&chars);
+        assert!(chars_back.equivalent(&chars));
+
+        let bits = explode_dfa(&dfa, |byte| {
+            (0..8).rev().map(|bit| byte >> bit & 1 == 1).collect()
+        });
+        let bits_back: Dfa<bool> = round_trip(&bits);
+        assert!(bits_back.equivalent(&bits));
+    }
+
+    #[test]
+    fn document() {
+        let mut dfa = Dfa::new();
+        dfa.initial_states.insert(0);
+        dfa.accept_states.extend([2, 1]);
+        dfa.link(0, 1, 'é');
+        dfa.link(1, 2, '"');
+        assert_eq!(
+            json(&dfa),
+            "{\"states\":[0,1,2],\"initial\":[0],\"accept\":[1,2],\"links\":[\
+             {\"from\":0,\"to\":1,\"symbol\":\"é\"},{\"from\":1,\"to\":2,\"symbol\":\"\\\"\"}]}\n"
+        );
+
+        let escaped = r#"{"initial":[0],"accept":[1],"links":[{"from":0,"to":1,"symbol":"é"},
+            {"symbol":"😀","to":1,"from":1}]}"#;
+        let read: Dfa<char> = automata_from_json(escaped).unwrap();
+        let symbols = read.links.iter().map(|link| link.symbol).collect_vec();
+        assert_eq!(symbols, ['é', '😀']);
+    }
+
+    #[test]
+    fn malformed() {
+        let link = |link: &str| format!(r#"{{"initial":[0],"accept":[1],"links":[{link}]}}"#);
+        let valid = link(r#"{"from":0,"to":1,"symbol":97}"#);
+        assert!(automata_from_json::<u8>(&valid).is_ok());
+
+        let mut texts = vec![
+            // truncated and trailing garbage
+            valid[..valid.len() - 1].to_string(),
+            valid[..valid.len() / 2].to_string(),
+            String::new(),
+            valid.clone() + ",",
+            valid.clone() + "{}",
+            // wrong types
+            "[]".to_string(),
+            "null".to_string(),
+            r#"{"accept":[1],"links":[]}"#.to_string(),
+            r#"{"initial":0,"accept":[1],"links":[]}"#.to_string(),
+            r#"{"initial":[0],"accept":[1],"links":{}}"#.to_string(),
+            r#"{"initial":[0],"accept":[1]}"#.to_string(),
+            link("[0,1,97]"),
+            link(r#"{"from":0,"to":1}"#),
+            link(r#"{"from":0,"symbol":97}"#),
+            link(r#"{"from":"0","to":1,"symbol":97}"#),
+            link(r#"{"from":0,"to":1,"symbol":"a"}"#),
+            link(r#"{"from":0,"to":1,"symbol":null}"#),
+            link(r#"{"from":0,"to":1,"symbol":256}"#),
+            link(r#"{"from":0,"to":1,"symbol":-1}"#),
+            link(r#"{"from":0,"to":1,"symbol":97.5}"#),
+        ];
+        // out of range states
+        for state in ["-1", "0.5", "4294967296", "1e300", "true"] {
+            texts.push(link(&format!(r#"{{"from":{state},"to":1,"symbol":97}}"#)));
+            texts.push(link(&format!(r#"{{"from":0,"to":{state},"symbol":97}}"#)));
+            texts.push(format!(
+                r#"{{"initial":[{state}],"accept":[1],"links":[]}}"#
+            ));
+        }
+        for text in &texts {
+            assert!(
+                matches!(automata_from_json::<u8>(text), Err(Error::Json(_))),
+                "{text:?} was accepted"
+            );
+        }
+
+        for symbol in ["97", r#""ab""#, r#""""#, "null"] {
+            let text = link(&format!(r#"{{"from":0,"to":1,"symbol":{symbol}}}"#));
+            assert!(automata_from_json::<char>(&text).is_err(), "{symbol}");
+        }
+        for symbol in ["1", r#""true""#] {
+            let text = link(&format!(r#"{{"from":0,"to":1,"symbol":{symbol}}}"#));
+            assert!(automata_from_json::<bool>(&text).is_err(), "{symbol}");
+        }
+        let epsilon = link(r#"{"from":0,"to":1,"symbol":null}"#);
+        assert!(automata_from_json::<MaybeSymbol<u8>>(&epsilon).is_ok());
+    }
+}
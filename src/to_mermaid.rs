@@ -0,0 +1,55 @@
+use std::io;
+
+use itertools::Itertools;
+
+use crate::{
+    automata::Automata,
+    to_dot::{edge_label, DotSymbol},
+};
+
+/// Write the automata as a Mermaid state diagram
+///
+/// Like [automata_to_dot](crate::to_dot::automata_to_dot), the links between two states are
+/// merged in a single edge labelled like a regex class.
+pub fn automata_to_mermaid<T: DotSymbol>(
+    out: &mut impl io::Write,
+    automata: &Automata<T>,
+) -> io::Result<()> {
+    writeln!(out, "stateDiagram-v2")?;
+    writeln!(out, "    direction LR")?;
+
+    for init in automata.initial_states.iter().sorted() {
+        writeln!(out, "    [*] --> s{init}")?;
+    }
+
+    let edges = automata
+        .links
+        .iter()
+        .map(|link| ((link.from, link.to), &link.symbol))
+        .into_group_map();
+    for ((from, to), symbols) in edges.into_iter().sorted_by_key(|(states, _)| *states) {
+        let label = escape(&edge_label(&symbols));
+        writeln!(out, "    s{from} --> s{to}: {label}")?;
+    }
+
+    for accept in automata.accept_states.iter().sorted() {
+        writeln!(out, "    s{accept} --> [*]")?;
+    }
+
+    Ok(())
+}
+
+/// Mermaid entity codes (`#35;`) for everything but letters, digits and spaces, which can't
+/// break the diagram syntax
+fn escape(label: &str) -> String {
+    label
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == ' ' {
+                c.to_string()
+            } else {
+                format!("#{};", c as u32)
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,250 @@
+use std::fmt::{self, Write};
+
+use regex_syntax::{
+    hir::{Class, ClassBytes, ClassBytesRange, ClassUnicode, Hir, HirKind, Literal},
+    utf8::Utf8Sequences,
+};
+
+/// Write a HIR using only the syntax shared by PCRE, YARA and `regex::bytes`
+///
+/// Unlike the [Display](std::fmt::Display) of [Hir], groups are plain `(...)` only added where
+/// precedence requires them, and bytes are written `\xNN` without `(?-u:...)`. Non-ASCII chars
+/// are written as their UTF-8 bytes.
+pub fn hir_to_portable(hir: &Hir) -> String {
+    let mut out = String::new();
+    write_hir(&mut out, hir, Precedence::Alternation).unwrap();
+    out
+}
+
+/// Write a HIR in the syntax of the `regex` crate
+///
+/// Same as the [Display](std::fmt::Display) of [Hir], but control chars (`\r`, `\n`...) are
+/// written `\xNN` instead of as they are, which would break line-oriented outputs.
+pub fn hir_to_rust(hir: &Hir) -> String {
+    let mut out = String::new();
+    for c in hir.to_string().chars() {
+        match c {
+            c if c.is_ascii_control() => write!(out, "\\x{:02X}", c as u32).unwrap(),
+            c if c.is_control() => write!(out, "\\x{{{:X}}}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Tightest construct the written HIR is nested in
+#[derive(PartialEq, PartialOrd)]
+enum Precedence {
+    Alternation,
+    Concat,
+    Repetition,
+}
+
+fn write_hir(out: &mut String, hir: &Hir, outer: Precedence) -> fmt::Result {
+    match hir.kind() {
+        HirKind::Empty => {
+            if outer == Precedence::Repetition {
+                out.write_str("()")?;
+            }
+        }
+        HirKind::Literal(Literal(bytes)) => {
+            let grouped = outer == Precedence::Repetition && bytes.len() > 1;
+            write_group(out, grouped, |out| {
+                bytes
+                    .iter()
+                    .try_for_each(|byte| write_byte(out, *byte, false))
+            })?;
+        }
+        HirKind::Class(Class::Unicode(class)) => {
+            let ranges = class.ranges();
+            if ranges.iter().any(|range| !range.end().is_ascii()) {
+                return write_alternation(out, &utf8_sequences(class), outer);
+            }
+            if let [range] = ranges {
+                if range.start() == range.end() {
+                    return write_char(out, range.start(), false);
+                }
+            }
+            out.write_char('[')?;
+            for range in ranges {
+                write_char(out, range.start(), true)?;
+                if range.start() != range.end() {
+                    if u32::from(range.end()) - u32::from(range.start()) > 1 {
+                        out.write_char('-')?;
+                    }
+                    write_char(out, range.end(), true)?;
+                }
+            }
+            if ranges.is_empty() {
+                out.write_str(r"^\x00-\xFF")?;
+            }
+            out.write_char(']')?;
+        }
+        HirKind::Class(Class::Bytes(class)) => {
+            let ranges = class.ranges();
+            if let [range] = ranges {
+                if range.start() == range.end() {
+                    return write_byte(out, range.start(), false);
+                }
+            }
+            out.write_char('[')?;
+            for range in ranges {
+                write_byte(out, range.start(), true)?;
+                if range.start() != range.end() {
+                    if range.end() - range.start() > 1 {
+                        out.write_char('-')?;
+                    }
+                    write_byte(out, range.end(), true)?;
+                }
+            }
+            if ranges.is_empty() {
+                out.write_str(r"^\x00-\xFF")?;
+            }
+            out.write_char(']')?;
+        }
+        HirKind::Look(_) => write!(out, "{hir}")?,
+        HirKind::Repetition(rep) => {
+            write_group(out, outer == Precedence::Repetition, |out| {
+                write_hir(out, &rep.sub, Precedence::Repetition)?;
+                match (rep.min, rep.max) {
+                    (0, Some(1)) => out.write_char('?')?,
+                    (0, None) => out.write_char('*')?,
+                    (1, None) => out.write_char('+')?,
+                    (min, None) => write!(out, "{{{min},}}")?,
+                    (min, Some(max)) if min == max => return write!(out, "{{{min}}}"),
+                    (min, Some(max)) => write!(out, "{{{min},{max}}}")?,
+                }
+                if !rep.greedy {
+                    out.write_char('?')?;
+                }
+                Ok(())
+            })?;
+        }
+        HirKind::Capture(capture) => {
+            write_group(out, true, |out| {
+                write_hir(out, &capture.sub, Precedence::Alternation)
+            })?;
+        }
+        HirKind::Concat(items) => {
+            write_group(out, outer == Precedence::Repetition, |out| {
+                items
+                    .iter()
+                    .try_for_each(|item| write_hir(out, item, Precedence::Concat))
+            })?;
+        }
+        HirKind::Alternation(items) => write_alternation(out, items, outer)?,
+    }
+    Ok(())
+}
+
+fn write_alternation(out: &mut String, items: &[Hir], outer: Precedence) -> fmt::Result {
+    if let [item] = items {
+        return write_hir(out, item, outer);
+    }
+    write_group(out, outer != Precedence::Alternation, |out| {
+        for (idx, item) in items.iter().enumerate() {
+            if idx > 0 {
+                out.write_char('|')?;
+            }
+            write_hir(out, item, Precedence::Alternation)?;
+        }
+        Ok(())
+    })
+}
+
+/// UTF-8 byte sequences of the chars of `class`, the portable syntax matches bytes
+fn utf8_sequences(class: &ClassUnicode) -> Vec<Hir> {
+    class
+        .iter()
+        .flat_map(|range| Utf8Sequences::new(range.start(), range.end()))
+        .map(|sequence| {
+            Hir::concat(
+                sequence
+                    .as_slice()
+                    .iter()
+                    .map(|bytes| {
+                        Hir::class(Class::Bytes(ClassBytes::new([ClassBytesRange::new(
+                            bytes.start,
+                            bytes.end,
+                        )])))
+                    })
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+fn write_group(
+    out: &mut String,
+    grouped: bool,
+    write_fn: impl FnOnce(&mut String) -> fmt::Result,
+) -> fmt::Result {
+    if grouped {
+        out.write_char('(')?;
+    }
+    write_fn(out)?;
+    if grouped {
+        out.write_char(')')?;
+    }
+    Ok(())
+}
+
+fn write_char(out: &mut String, c: char, in_class: bool) -> fmt::Result {
+    let meta = if in_class {
+        r"\[]^-/"
+    } else {
+        r"\.+*?()|[]{}^$/"
+    };
+    if c.is_ascii_control() || (c.is_ascii_whitespace() && c != ' ') {
+        write!(out, "\\x{:02X}", c as u32)
+    } else {
+        if meta.contains(c) {
+            out.write_char('\\')?;
+        }
+        out.write_char(c)
+    }
+}
+
+fn write_byte(out: &mut String, byte: u8, in_class: bool) -> fmt::Result {
+    if byte.is_ascii() {
+        write_char(out, byte as char, in_class)
+    } else {
+        write!(out, "\\x{byte:02X}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex_syntax::ParserBuilder;
+
+    use super::*;
+
+    fn portable(regex: &str) -> String {
+        hir_to_portable(&ParserBuilder::new().build().parse(regex).unwrap())
+    }
+
+    #[test]
+    fn non_ascii_as_utf8_bytes() {
+        assert_eq!(portable("é+"), r"(\xC3\xA9)+");
+        assert_eq!(portable("[aé]"), r"a|\xC3\xA9");
+        assert_eq!(portable("x[é-ë]"), r"x\xC3[\xA9-\xAB]");
+    }
+
+    #[test]
+    fn rust_control_chars() {
+        let hir = |regex: &str| ParserBuilder::new().build().parse(regex).unwrap();
+        assert_eq!(hir_to_rust(&hir(r"a\r\n")), r"(?:a\x0D\x0A)");
+        assert_eq!(hir_to_rust(&hir(r"[\t\n ]")), r"[\x09\x0A ]");
+        assert_eq!(hir_to_rust(&hir(r"\x{85}é")), r"(?:\x{85}é)");
+        // parsed back to the same HIR
+        for regex in [r"a\r\n", r"[\t\n ]+x", r"\x{85}é"] {
+            let rust = hir_to_rust(&hir(regex));
+            assert_eq!(hir(&rust), hir(regex), "{regex}");
+        }
+    }
+
+    #[test]
+    fn empty_class() {
+        assert_eq!(portable("[a&&b]"), r"[^\x00-\xFF]");
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{
+    automata::Automata,
+    to_dot::{automata_to_dot, DotOptions, DotSymbol},
+    to_json::{automata_to_json, JsonSymbol},
+    to_mermaid::automata_to_mermaid,
+};
+
+/// Language of the graphs of a [GraphTrace]
+#[derive(Debug, Clone, Copy, Default)]
+pub enum GraphFormat {
+    #[default]
+    Dot,
+    Mermaid,
+    Json,
+}
+
+impl GraphFormat {
+    /// Usual file extension
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::Mermaid => "mmd",
+            GraphFormat::Json => "json",
+        }
+    }
+}
+
+/// Graphs of the automata built along a conversion, in order
+#[derive(Debug, Clone, Default)]
+pub struct GraphTrace {
+    pub format: GraphFormat,
+    pub dot_options: DotOptions,
+    /// Name of the step and graph of the automata it built
+    pub graphs: Vec<(String, String)>,
+}
+
+impl GraphTrace {
+    pub fn new(format: GraphFormat, dot_options: DotOptions) -> Self {
+        Self {
+            format,
+            dot_options,
+            graphs: Vec::new(),
+        }
+    }
+
+    pub fn push<T>(&mut self, name: impl Into<String>, automata: &Automata<T>)
+    where
+        T: DotSymbol + JsonSymbol,
+    {
+        let mut graph = Vec::new();
+        match self.format {
+            GraphFormat::Dot => automata_to_dot(&mut graph, automata, &self.dot_options),
+            GraphFormat::Mermaid => automata_to_mermaid(&mut graph, automata),
+            GraphFormat::Json => automata_to_json(&mut graph, automata),
+        }
+        .unwrap();
+        let graph = String::from_utf8(graph).unwrap();
+        self.graphs.push((name.into(), graph));
+    }
+}
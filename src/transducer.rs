@@ -0,0 +1,433 @@
+use std::{collections::HashMap, hash::Hash};
+
+use itertools::{Itertools, Position};
+
+use crate::{
+    automata::{Automata, Dfa, Link, MaybeSymbol, Nfa, State},
+    counter::Counter,
+    error::Error,
+    limits::{Limit, Limits},
+};
+
+/// Read at most one input symbol and write a sequence of output symbols
+#[derive(Debug, Clone)]
+pub struct Transduction<I, O> {
+    pub input: MaybeSymbol<I>,
+    pub output: Vec<O>,
+}
+
+/// Finite-state transducer, the strings it writes while reading a string from an initial state
+/// to an accept state are the transductions of this string
+pub type Transducer<I, O> = Automata<Transduction<I, O>>;
+
+impl<I, O> Transducer<I, O> {
+    /// One-state transducer replacing each of the `inputs` by any of the sequences returned by
+    /// `alternatives_fn`
+    pub fn substitution<AF>(inputs: impl IntoIterator<Item = I>, alternatives_fn: AF) -> Self
+    where
+        AF: Fn(&I) -> Vec<Vec<O>>,
+        I: Clone,
+    {
+        let mut transducer = Self::new();
+        transducer.initial_states.insert(0);
+        transducer.accept_states.insert(0);
+
+        for input in inputs {
+            for output in alternatives_fn(&input) {
+                let input = MaybeSymbol::Symbol(input.clone());
+                transducer.link(0, 0, Transduction { input, output });
+            }
+        }
+
+        transducer
+    }
+
+    /// One-state transducer replacing each of the `inputs` by any of the strings accepted by the
+    /// DFA returned by `automata_fn`, for sets of replacements too large to list
+    pub fn automata_substitution<AF>(inputs: impl IntoIterator<Item = I>, automata_fn: AF) -> Self
+    where
+        AF: Fn(&I) -> Dfa<O>,
+        I: Clone,
+    {
+        let mut transducer = Self::new();
+        transducer.initial_states.insert(0);
+        transducer.accept_states.insert(0);
+
+        let mut offset = 1;
+        for input in inputs {
+            let replacements = automata_fn(&input);
+            let next_offset = offset + replacements.max_state() + 1;
+            for initial in &replacements.initial_states {
+                let input = MaybeSymbol::Symbol(input.clone());
+                let output = Vec::new();
+                transducer.link(0, initial + offset, Transduction { input, output });
+            }
+            for link in replacements.links {
+                let input = MaybeSymbol::Epsilon;
+                let output = vec![link.symbol];
+                transducer.link(
+                    link.from + offset,
+                    link.to + offset,
+                    Transduction { input, output },
+                );
+            }
+            for accept in &replacements.accept_states {
+                let input = MaybeSymbol::Epsilon;
+                let output = Vec::new();
+                transducer.link(accept + offset, 0, Transduction { input, output });
+            }
+            offset = next_offset;
+        }
+
+        transducer
+    }
+
+    /// Strings written while reading a string accepted by `nfa`
+    pub fn apply(&self, nfa: &Nfa<I>) -> Nfa<O>
+    where
+        I: Eq + Hash,
+        O: Clone,
+    {
+        self.apply_limited(nfa, &Limits::default())
+            .expect("no limits")
+    }
+
+    /// [Transducer::apply], failing when the links of the image go over `limits`
+    pub fn apply_limited(&self, nfa: &Nfa<I>, limits: &Limits) -> Result<Nfa<O>, Error>
+    where
+        I: Eq + Hash,
+        O: Clone,
+    {
+        let nfa_links = nfa
+            .links
+            .iter()
+            .map(|link| (link.from, link))
+            .into_group_map();
+        let (reading, not_reading) = self.links_by_input();
+
+        let mut image = Nfa::new();
+        let mut product = ProductStates::new();
+
+        for initial in nfa
+            .initial_states
+            .iter()
+            .cartesian_product(&self.initial_states)
+        {
+            let initial = product.state((*initial.0, *initial.1));
+            image.initial_states.insert(initial);
+        }
+
+        while let Some(((nfa_state, state), from)) = product.next_to_explore() {
+            if nfa.accept_states.contains(&nfa_state) && self.accept_states.contains(&state) {
+                image.accept_states.insert(from);
+            }
+
+            for nfa_link in nfa_links.get(&nfa_state).into_iter().flatten() {
+                match &nfa_link.symbol {
+                    MaybeSymbol::Epsilon => {
+                        let to = product.state((nfa_link.to, state));
+                        image.link(from, to, MaybeSymbol::Epsilon);
+                    }
+                    MaybeSymbol::Symbol(symbol) => {
+                        for link in reading.get(&(state, symbol)).into_iter().flatten() {
+                            let to = product.state((nfa_link.to, link.to));
+                            write_output(&mut image, &mut product, from, to, &link.symbol.output);
+                        }
+                    }
+                }
+            }
+
+            for link in not_reading.get(&state).into_iter().flatten() {
+                let to = product.state((nfa_state, link.to));
+                write_output(&mut image, &mut product, from, to, &link.symbol.output);
+            }
+            limits.check(Limit::Links, image.links.len())?;
+        }
+
+        Ok(image)
+    }
+
+    /// Transducer writing the strings read by this one, and reading the strings it writes
+    pub fn inverse(&self) -> Transducer<O, I>
+    where
+        I: Clone,
+        O: Clone,
+    {
+        let mut inverse = Transducer::new();
+        inverse.initial_states = self.initial_states.clone();
+        inverse.accept_states = self.accept_states.clone();
+
+        let mut counter = Counter::new(self.max_state() + 1);
+
+        for link in &self.links {
+            let output = match &link.symbol.input {
+                MaybeSymbol::Symbol(input) => vec![input.clone()],
+                MaybeSymbol::Epsilon => Vec::new(),
+            };
+
+            let Some((last, inputs)) = link.symbol.output.split_last() else {
+                let input = MaybeSymbol::Epsilon;
+                inverse.link(link.from, link.to, Transduction { input, output });
+                continue;
+            };
+
+            // the output is written once the whole input sequence is read
+            let mut prev = link.from;
+            for input in inputs {
+                let next = counter.next();
+                let input = MaybeSymbol::Symbol(input.clone());
+                inverse.link(
+                    prev,
+                    next,
+                    Transduction {
+                        input,
+                        output: Vec::new(),
+                    },
+                );
+                prev = next;
+            }
+            let input = MaybeSymbol::Symbol(last.clone());
+            inverse.link(prev, link.to, Transduction { input, output });
+        }
+
+        inverse
+    }
+
+    /// Transducer writing what `next` writes while reading what this one writes
+    pub fn compose<P>(&self, next: &Transducer<O, P>) -> Transducer<I, P>
+    where
+        I: Clone,
+        O: Clone + Eq + Hash,
+        P: Clone,
+    {
+        // one output symbol per link, so each link is matched by at most one link of `next`
+        let mut counter = Counter::new(self.max_state() + 1);
+        let mut single_outputs: Automata<(MaybeSymbol<I>, MaybeSymbol<O>)> = Automata::new();
+        for link in &self.links {
+            let mut input = link.symbol.input.clone();
+            let mut prev = link.from;
+            for (position, output) in link.symbol.output.iter().with_position() {
+                let next = match position {
+                    Position::Last | Position::Only => link.to,
+                    Position::First | Position::Middle => counter.next(),
+                };
+                let output = MaybeSymbol::Symbol(output.clone());
+                single_outputs.link(prev, next, (input, output));
+                input = MaybeSymbol::Epsilon;
+                prev = next;
+            }
+            if link.symbol.output.is_empty() {
+                single_outputs.link(prev, link.to, (input, MaybeSymbol::Epsilon));
+            }
+        }
+        let single_links = single_outputs
+            .links
+            .iter()
+            .map(|link| (link.from, link))
+            .into_group_map();
+        let (next_reading, next_not_reading) = next.links_by_input();
+
+        let mut composed = Transducer::new();
+        let mut product = ProductStates::new();
+
+        for initial in self
+            .initial_states
+            .iter()
+            .cartesian_product(&next.initial_states)
+        {
+            let initial = product.state((*initial.0, *initial.1));
+            composed.initial_states.insert(initial);
+        }
+
+        while let Some(((state, next_state), from)) = product.next_to_explore() {
+            if self.accept_states.contains(&state) && next.accept_states.contains(&next_state) {
+                composed.accept_states.insert(from);
+            }
+
+            for link in single_links.get(&state).into_iter().flatten() {
+                let (input, output) = &link.symbol;
+                match output {
+                    MaybeSymbol::Epsilon => {
+                        let to = product.state((link.to, next_state));
+                        let input = input.clone();
+                        composed.link(
+                            from,
+                            to,
+                            Transduction {
+                                input,
+                                output: Vec::new(),
+                            },
+                        );
+                    }
+                    MaybeSymbol::Symbol(symbol) => {
+                        for next_link in next_reading
+                            .get(&(next_state, symbol))
+                            .into_iter()
+                            .flatten()
+                        {
+                            let to = product.state((link.to, next_link.to));
+                            let transduction = Transduction {
+                                input: input.clone(),
+                                output: next_link.symbol.output.clone(),
+                            };
+                            composed.link(from, to, transduction);
+                        }
+                    }
+                }
+            }
+
+            for next_link in next_not_reading.get(&next_state).into_iter().flatten() {
+                let to = product.state((state, next_link.to));
+                let transduction = Transduction {
+                    input: MaybeSymbol::Epsilon,
+                    output: next_link.symbol.output.clone(),
+                };
+                composed.link(from, to, transduction);
+            }
+        }
+
+        composed
+    }
+
+    /// Links indexed by their `from` state and input symbol, and the links reading nothing
+    /// indexed by their `from` state
+    #[allow(clippy::type_complexity)]
+    fn links_by_input(
+        &self,
+    ) -> (
+        HashMap<(State, &I), Vec<&Link<Transduction<I, O>>>>,
+        HashMap<State, Vec<&Link<Transduction<I, O>>>>,
+    )
+    where
+        I: Eq + Hash,
+    {
+        let mut reading: HashMap<_, Vec<_>> = HashMap::new();
+        let mut not_reading: HashMap<_, Vec<_>> = HashMap::new();
+        for link in &self.links {
+            match &link.symbol.input {
+                MaybeSymbol::Symbol(input) => reading.entry((link.from, input)).or_default(),
+                MaybeSymbol::Epsilon => not_reading.entry(link.from).or_default(),
+            }
+            .push(link);
+        }
+        (reading, not_reading)
+    }
+}
+
+/// Link `from` to `to` by a path writing `output`
+fn write_output<K, O: Clone>(
+    nfa: &mut Nfa<O>,
+    product: &mut ProductStates<K>,
+    from: State,
+    to: State,
+    output: &[O],
+) {
+    let Some((last, firsts)) = output.split_last() else {
+        nfa.link(from, to, MaybeSymbol::Epsilon);
+        return;
+    };
+
+    let mut prev = from;
+    for symbol in firsts {
+        let next = product.counter.next();
+        nfa.link(prev, next, MaybeSymbol::Symbol(symbol.clone()));
+        prev = next;
+    }
+    nfa.link(prev, to, MaybeSymbol::Symbol(last.clone()));
+}
+
+/// States of a product of automata, numbered as they are discovered
+struct ProductStates<K> {
+    states: HashMap<K, State>,
+    to_explore: Vec<K>,
+    counter: Counter,
+}
+
+impl<K: Copy + Eq + Hash> ProductStates<K> {
+    fn new() -> Self {
+        Self {
+            states: HashMap::new(),
+            to_explore: Vec::new(),
+            counter: Counter::new(0),
+        }
+    }
+
+    fn state(&mut self, key: K) -> State {
+        *self.states.entry(key).or_insert_with(|| {
+            self.to_explore.push(key);
+            self.counter.next()
+        })
+    }
+
+    fn next_to_explore(&mut self) -> Option<(K, State)> {
+        let key = self.to_explore.pop()?;
+        Some((key, self.states[&key]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{determine::determine_and_min_nfa, hir_to_nfa::regex_to_nfa};
+
+    fn dfa(regex: &str) -> Dfa<u8> {
+        determine_and_min_nfa(regex_to_nfa(regex).unwrap())
+    }
+
+    /// `a` to `ab` or `ba`, `b` to `c`, anything else dropped
+    fn transducer() -> Transducer<u8, u8> {
+        Transducer::substitution(*b"abc", |byte| match byte {
+            b'a' => vec![b"ab".to_vec(), b"ba".to_vec()],
+            b'b' => vec![b"c".to_vec()],
+            _ => vec![Vec::new()],
+        })
+    }
+
+    #[test]
+    fn apply() {
+        let applied = determine_and_min_nfa(transducer().apply(&dfa("a+c").to_nfa()));
+        assert!(applied.equivalent(&dfa("(ab|ba)+")));
+    }
+
+    #[test]
+    fn compose_applies_both() {
+        let t = transducer();
+        for regex in ["a", "ab*c", "(a|b)+", "c*"] {
+            let nfa = dfa(regex).to_nfa();
+            let twice = determine_and_min_nfa(t.apply(&t.apply(&nfa)));
+            let composed = determine_and_min_nfa(t.compose(&t).apply(&nfa));
+            assert!(composed.equivalent(&twice), "{regex}");
+        }
+        let composed = determine_and_min_nfa(t.compose(&t).apply(&dfa("a").to_nfa()));
+        assert!(composed.equivalent(&dfa("abc|bac|cab|cba")));
+    }
+
+    #[test]
+    fn inverse_reads_back() {
+        // without `c`, the substitution is one-to-one
+        let t = Transducer::substitution(*b"ab", |byte| match byte {
+            b'a' => vec![b"xy".to_vec()],
+            _ => vec![b"z".to_vec(), b"zz".to_vec()],
+        });
+        for regex in ["a", "ab*", "(a|b)+a", ""] {
+            let nfa = dfa(regex).to_nfa();
+            let back = determine_and_min_nfa(t.inverse().apply(&t.apply(&nfa)));
+            assert!(back.equivalent(&dfa(regex)), "{regex}");
+        }
+        let inverse = determine_and_min_nfa(t.inverse().apply(&dfa("xyzz").to_nfa()));
+        assert!(inverse.equivalent(&dfa("abb|ab")));
+    }
+
+    #[test]
+    fn apply_limited() {
+        let limits = Limits {
+            max_links: Some(100),
+            ..Limits::default()
+        };
+        let nfa = dfa("a{40}").to_nfa();
+        // 4 links per `a` for its 2 renderings, 1 per `b`
+        assert!(transducer().apply_limited(&nfa, &limits).is_err());
+        let nfa = dfa("b{40}").to_nfa();
+        assert!(transducer().apply_limited(&nfa, &limits).is_ok());
+    }
+}
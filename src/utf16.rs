@@ -0,0 +1,171 @@
+use std::ops::RangeInclusive;
+
+use crate::{
+    automata::{Dfa, MaybeSymbol, State},
+    counter::Counter,
+    determine::determine_and_min_nfa_limited,
+    error::Error,
+    limits::Limits,
+    transducer::{Transducer, Transduction},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    /// Bytes of a UTF-16 code unit
+    fn unit_bytes(&self, unit: u32) -> Vec<u8> {
+        let [high, low] = (unit as u16).to_be_bytes();
+        match self {
+            Endianness::Little => vec![low, high],
+            Endianness::Big => vec![high, low],
+        }
+    }
+}
+
+/// Encode the UTF-8 text of the pattern in UTF-16 ("wide" strings)
+///
+/// Chars outside the BMP are written as surrogate pairs. Bytes that are not valid UTF-8 can't be
+/// part of a text, the strings containing them are dropped.
+pub fn utf16_dfa(dfa: &Dfa<u8>, endianness: Endianness) -> Dfa<u8> {
+    utf16_dfa_limited(dfa, endianness, &Limits::default()).expect("no limits")
+}
+
+/// [utf16_dfa], failing when the automata built go over `limits`
+pub fn utf16_dfa_limited(
+    dfa: &Dfa<u8>,
+    endianness: Endianness,
+    limits: &Limits,
+) -> Result<Dfa<u8>, Error> {
+    let encoded = utf8_to_utf16(endianness).apply_limited(&dfa.to_nfa(), limits)?;
+    determine_and_min_nfa_limited(encoded, limits)
+}
+
+/// Transducer reading well-formed UTF-8 (RFC 3629) and writing the UTF-16 code units of its chars
+///
+/// A code unit is written after its last UTF-8 byte, so the states between the bytes of a char
+/// remember the bits of the code point read so far.
+fn utf8_to_utf16(endianness: Endianness) -> Transducer<u8, u8> {
+    let mut transducer = Transducer::new();
+    transducer.initial_states.insert(0);
+    transducer.accept_states.insert(0);
+    let mut counter = Counter::new(1);
+    let mut link = |from: State, to: State, byte: u8, output: Vec<u8>| {
+        let input = MaybeSymbol::Symbol(byte);
+        transducer.link(from, to, Transduction { input, output });
+    };
+    let payload = |byte: u8| (byte & 0x3F) as u32;
+
+    for byte in 0..=0x7F {
+        link(0, 0, byte, endianness.unit_bytes(byte as u32));
+    }
+
+    for lead in 0xC2..=0xDF {
+        let first = counter.next();
+        link(0, first, lead, Vec::new());
+        for last in CONTINUATION {
+            let code_point = ((lead & 0x1F) as u32) << 6 | payload(last);
+            link(first, 0, last, endianness.unit_bytes(code_point));
+        }
+    }
+
+    for lead in 0xE0..=0xEF {
+        let first = counter.next();
+        link(0, first, lead, Vec::new());
+        for second in second_byte(lead) {
+            let next = counter.next();
+            link(first, next, second, Vec::new());
+            for last in CONTINUATION {
+                let code_point = ((lead & 0xF) as u32) << 12 | payload(second) << 6 | payload(last);
+                link(next, 0, last, endianness.unit_bytes(code_point));
+            }
+        }
+    }
+
+    // the high surrogate is written after the third byte, the low one only needs the last 4 bits
+    // of the third byte
+    let low_surrogates: Vec<State> = (0..16).map(|_| counter.next()).collect();
+    for (low_bits, state) in low_surrogates.iter().enumerate() {
+        for last in CONTINUATION {
+            let low = 0xDC00 | (low_bits as u32) << 6 | payload(last);
+            link(*state, 0, last, endianness.unit_bytes(low));
+        }
+    }
+    for lead in 0xF0..=0xF4 {
+        let first = counter.next();
+        link(0, first, lead, Vec::new());
+        for second in second_byte(lead) {
+            let next = counter.next();
+            link(first, next, second, Vec::new());
+            for third in CONTINUATION {
+                // code point without its last 6 bits
+                let high_bits = ((lead & 0x7) as u32) << 12 | payload(second) << 6 | payload(third);
+                let high = 0xD800 + ((high_bits >> 4) - 0x40);
+                let low_state = low_surrogates[(third & 0xF) as usize];
+                link(next, low_state, third, endianness.unit_bytes(high));
+            }
+        }
+    }
+
+    transducer
+}
+
+const CONTINUATION: RangeInclusive<u8> = 0x80..=0xBF;
+
+/// Second bytes after `lead`, excluding overlong encodings, surrogates and code points over
+/// U+10FFFF
+fn second_byte(lead: u8) -> RangeInclusive<u8> {
+    match lead {
+        0xE0 => 0xA0..=0xBF,
+        0xED => 0x80..=0x9F,
+        0xF0 => 0x90..=0xBF,
+        0xF4 => 0x80..=0x8F,
+        _ => CONTINUATION,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{determine::determine_and_min_nfa, hir_to_nfa::regex_to_nfa};
+
+    fn dfa(regex: &str) -> Dfa<u8> {
+        determine_and_min_nfa(regex_to_nfa(regex).unwrap())
+    }
+
+    #[test]
+    fn code_units() {
+        let cases = [
+            ("A", r"A\x00", r"\x00A"),
+            // é
+            (r"\xC3\xA9", r"\xE9\x00", r"\x00\xE9"),
+            // €
+            (r"\xE2\x82\xAC", r"\xAC\x20", r"\x20\xAC"),
+            // U+1F600, a surrogate pair
+            (
+                r"\xF0\x9F\x98\x80",
+                r"\x3D\xD8\x00\xDE",
+                r"\xD8\x3D\xDE\x00",
+            ),
+            (r"[\x00-\x7F]", r"[\x00-\x7F]\x00", r"\x00[\x00-\x7F]"),
+        ];
+        for (utf8, little, big) in cases {
+            let little_dfa = utf16_dfa(&dfa(utf8), Endianness::Little);
+            assert!(little_dfa.equivalent(&dfa(little)), "{utf8}");
+            let big_dfa = utf16_dfa(&dfa(utf8), Endianness::Big);
+            assert!(big_dfa.equivalent(&dfa(big)), "{utf8}");
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_is_dropped() {
+        // lone continuation, overlong `/`, surrogate, over U+10FFFF
+        for invalid in [r"\x80", r"\xC0\xAF", r"\xED\xA0\x80", r"\xF4\x90\x80\x80"] {
+            let utf16 = utf16_dfa(&dfa(&format!("a{invalid}|b")), Endianness::Little);
+            assert!(utf16.equivalent(&dfa(r"b\x00")), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{
+    automata::{Automata, Dfa, State},
+    determine::determine_and_min_nfa_limited,
+    error::Error,
+    explode::explode_dfa,
+    limits::{Limit, Limits},
+};
+
+/// XOR every symbol with `key`
+pub fn xor_dfa(dfa: &Dfa<u8>, key: u8) -> Dfa<u8> {
+    explode_dfa(dfa, |byte: &u8| vec![byte ^ key])
+}
+
+/// Match the pattern XORed with any of the `keys` (e.g. `0..=255` when the key is unknown)
+pub fn xor_any_dfa(dfa: &Dfa<u8>, keys: impl IntoIterator<Item = u8>) -> Dfa<u8> {
+    xor_any_dfa_limited(dfa, keys, &Limits::default()).expect("no limits")
+}
+
+/// [xor_any_dfa], failing when the automata built go over `limits`
+pub fn xor_any_dfa_limited(
+    dfa: &Dfa<u8>,
+    keys: impl IntoIterator<Item = u8>,
+    limits: &Limits,
+) -> Result<Dfa<u8>, Error> {
+    let mut xored = Vec::new();
+    let mut links = 0;
+    for key in keys {
+        let dfa = xor_dfa(dfa, key);
+        links += dfa.links.len();
+        limits.check(Limit::Links, links)?;
+        xored.push(dfa);
+    }
+    determine_and_min_nfa_limited(Automata::union(xored).to_nfa(), limits)
+}
+
+/// XOR the pattern with the repeating `key` (e.g. `DE AD BE EF`), the pattern can start at any
+/// position of the key
+///
+/// The DFA is multiplied by a cycle of `key.len()` states tracking the position in the key.
+pub fn xor_repeating_dfa(dfa: &Dfa<u8>, key: &[u8]) -> Dfa<u8> {
+    xor_repeating_dfa_limited(dfa, key, &Limits::default()).expect("no limits")
+}
+
+/// [xor_repeating_dfa], failing when the automata built go over `limits`
+pub fn xor_repeating_dfa_limited(
+    dfa: &Dfa<u8>,
+    key: &[u8],
+    limits: &Limits,
+) -> Result<Dfa<u8>, Error> {
+    assert!(!key.is_empty());
+
+    let len = key.len() as State;
+    let product_state = |state: State, phase: State| state * len + phase;
+
+    let mut product = Dfa::new();
+    for phase in 0..len {
+        product.initial_states.extend(
+            dfa.initial_states
+                .iter()
+                .map(|state| product_state(*state, phase)),
+        );
+        product.accept_states.extend(
+            dfa.accept_states
+                .iter()
+                .map(|state| product_state(*state, phase)),
+        );
+        for link in &dfa.links {
+            product.link(
+                product_state(link.from, phase),
+                product_state(link.to, (phase + 1) % len),
+                link.symbol ^ key[phase as usize],
+            );
+        }
+        limits.check(Limit::Links, product.links.len())?;
+    }
+
+    determine_and_min_nfa_limited(product.to_nfa(), limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{determine::determine_and_min_nfa, hir_to_nfa::regex_to_nfa};
+
+    fn dfa(regex: &str) -> Dfa<u8> {
+        determine_and_min_nfa(regex_to_nfa(regex).unwrap())
+    }
+
+    fn accepts(dfa: &Dfa<u8>, text: &[u8]) -> bool {
+        let mut states = dfa.initial_states.clone();
+        for byte in text {
+            states = dfa
+                .links
+                .iter()
+                .filter(|link| states.contains(&link.from) && link.symbol == *byte)
+                .map(|link| link.to)
+                .collect();
+        }
+        states.iter().any(|state| dfa.accept_states.contains(state))
+    }
+
+    fn xor(text: &[u8], key: &[u8]) -> Vec<u8> {
+        text.iter()
+            .zip(key.iter().cycle())
+            .map(|(byte, key)| byte ^ key)
+            .collect()
+    }
+
+    #[test]
+    fn single_byte_key() {
+        let xored = xor_dfa(&dfa("secret[0-9]"), 0x5A);
+        assert!(accepts(&xored, &xor(b"secret7", &[0x5A])));
+        // the plaintext, another key, a byte off
+        assert!(!accepts(&xored, b"secret7"));
+        assert!(!accepts(&xored, &xor(b"secret7", &[0x5B])));
+        assert!(!accepts(&xored, &xor(b"secreT7", &[0x5A])));
+        assert!(!accepts(&xored, &xor(b"secretx", &[0x5A])));
+    }
+
+    #[test]
+    fn any_single_byte_key() {
+        let xored = xor_any_dfa(&dfa("secret"), 0..=255);
+        for key in [0x00, 0x41, 0xFF] {
+            assert!(accepts(&xored, &xor(b"secret", &[key])), "{key}");
+        }
+        // the key changes in the middle
+        let mut near_miss = xor(b"secret", &[0x41]);
+        near_miss[3] ^= 1;
+        assert!(!accepts(&xored, &near_miss));
+
+        let some_keys = xor_any_dfa(&dfa("secret"), [0x10, 0x20]);
+        assert!(accepts(&some_keys, &xor(b"secret", &[0x20])));
+        assert!(!accepts(&some_keys, &xor(b"secret", &[0x30])));
+    }
+
+    #[test]
+    fn repeating_key_alignments() {
+        let key = [0xDE, 0xAD, 0xBE, 0xEF];
+        // shorter and longer than the key
+        for text in [&b"ab"[..], b"secret!"] {
+            let regex = String::from_utf8(text.to_vec()).unwrap();
+            let xored = xor_repeating_dfa(&dfa(&regex), &key);
+            for offset in 0..key.len() {
+                let mut aligned = key;
+                aligned.rotate_left(offset);
+                assert!(accepts(&xored, &xor(text, &aligned)), "{regex} at {offset}");
+
+                // the key skips a byte in the middle
+                let mut skipped = xor(text, &aligned);
+                skipped[1] = text[1] ^ aligned[2];
+                assert!(!accepts(&xored, &skipped), "{regex} at {offset}");
+            }
+            // the key read backward
+            let mut backward = key;
+            backward.reverse();
+            assert!(!accepts(&xored, &xor(text, &backward)), "{regex}");
+        }
+    }
+
+    #[test]
+    fn repeating_single_byte_key() {
+        let pattern = dfa("ab[0-9]+");
+        let repeating = xor_repeating_dfa(&pattern, &[0x5A]);
+        assert!(repeating.equivalent(&determine_and_min_nfa(xor_dfa(&pattern, 0x5A).to_nfa())));
+    }
+}